@@ -106,6 +106,11 @@ async fn assets(Path(path): Path<String>, uri: Uri) -> impl IntoResponse {
     StatusCode::NOT_FOUND.into_response()
 }
 
+async fn shutdown_signal() {
+    _ = tokio::signal::ctrl_c().await;
+    tracing::info!("shutting down");
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::registry()
@@ -125,12 +130,14 @@ async fn main() -> anyhow::Result<()> {
         .try_init()
         .ok();
 
-    let runtime = js::Runtime::new(js::RuntimeConfig {
+    let runtime = js::Runtime::try_new(js::RuntimeConfig {
         workers: 1,
         js_src_dir: Some(include_dir::include_dir!("$CARGO_MANIFEST_DIR/src-web")),
-        pages_dir: "pages/server".into(),
+        pages_dirs: vec!["pages/server".into()],
+        console_tracing: true,
         ..Default::default()
-    });
+    })
+    .unwrap_or_else(|err| panic!("{err}"));
 
     let app = Router::new()
         .route("/", get(index))
@@ -138,13 +145,20 @@ async fn main() -> anyhow::Result<()> {
         .route("/app", get(spa_index))
         .route("/app/{*path}", get(assets))
         .route("/assets/{*path}", get(assets))
-        .with_state(AppState { runtime });
+        .with_state(AppState {
+            runtime: runtime.clone(),
+        });
 
     let listener = TcpListener::bind(format!("127.0.0.1:4000")).await?;
 
     tracing::info!("listening on http://{}", listener.local_addr()?);
 
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    // let in-flight renders finish before exiting
+    runtime.shutdown(std::time::Duration::from_secs(5)).await;
 
     Ok(())
 }
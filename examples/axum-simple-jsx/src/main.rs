@@ -48,6 +48,7 @@ async fn function(runtime: js::Runtime) -> impl IntoResponse {
         .execute_script(js::Script::Function {
             args: Some(json!({"a": 1, "b": 1})),
             code: "console.log('sum'); args.a + args.b".into(),
+            timeout: None,
         })
         .await
         .map(Json)
@@ -69,6 +70,11 @@ pub struct AppState {
     runtime: js::Runtime,
 }
 
+async fn shutdown_signal() {
+    _ = tokio::signal::ctrl_c().await;
+    tracing::info!("shutting down");
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::registry()
@@ -88,22 +94,31 @@ async fn main() -> anyhow::Result<()> {
         .try_init()
         .ok();
 
-    let runtime = js::Runtime::new(js::RuntimeConfig {
+    let runtime = js::Runtime::try_new(js::RuntimeConfig {
         workers: 1,
         js_src_dir: Some(include_dir::include_dir!("$CARGO_MANIFEST_DIR/src-js")),
+        console_tracing: true,
         ..Default::default()
-    });
+    })
+    .unwrap_or_else(|err| panic!("{err}"));
     let app = Router::new()
         .route("/", get(index))
         .route("/items", get(items))
         .route("/function", get(function))
-        .with_state(AppState { runtime });
+        .with_state(AppState {
+            runtime: runtime.clone(),
+        });
 
     let listener = TcpListener::bind(format!("127.0.0.1:4000")).await?;
 
     tracing::info!("listening on http://{}", listener.local_addr()?);
 
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    // let in-flight renders finish before exiting
+    runtime.shutdown(std::time::Duration::from_secs(5)).await;
 
     Ok(())
 }
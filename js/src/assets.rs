@@ -0,0 +1,243 @@
+use regex::Regex;
+use serde_json::{Value, json};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+/// `from "./icon.svg" with { type: "bytes" }`, also `import "..." with` and
+/// the older `assert`, or `import("./data.json", { with: { type: "json" } })`.
+static IMPORT_ATTRIBUTES: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r#"\b(?P<keyword>from|import)\s*(?P<specifier>"[^"\n]*"|'[^'\n]*')\s*(?:with|assert)\s*\{\s*type\s*:\s*["'](?P<type>[^"'\n]*)["']\s*,?\s*\}"#,
+        "|",
+        r#"\bimport\s*\(\s*(?P<dynamic>"[^"\n]*"|'[^'\n]*')\s*,\s*\{\s*(?:with|assert)\s*:\s*\{\s*type\s*:\s*["'](?P<dynamic_type>[^"'\n]*)["']\s*,?\s*\}\s*,?\s*\}\s*\)"#,
+    ))
+    .unwrap()
+});
+
+/// QuickJS can't parse import attributes and the module loader only gets the
+/// name, so the type moves into the specifier: `"./icon.svg?bytes"`.
+///
+/// Strings, comments and the like are left alone. Newlines are kept, so
+/// source maps still line up.
+pub(crate) fn rewrite_import_attributes(code: &str) -> Cow<'_, str> {
+    let literals = literals(code);
+    let in_literal = |offset: usize| {
+        let i = literals.partition_point(|range| range.end <= offset);
+        literals.get(i).is_some_and(|range| range.contains(&offset))
+    };
+
+    let mut rewritten = String::new();
+    let mut last = 0;
+
+    for captures in IMPORT_ATTRIBUTES.captures_iter(code) {
+        let all = captures.get(0).unwrap();
+        if in_literal(all.start()) {
+            continue;
+        }
+
+        let newlines = "\n".repeat(all.as_str().matches('\n').count());
+        let replacement = match captures.name("dynamic") {
+            Some(specifier) => {
+                let specifier = with_type(specifier.as_str(), &captures["dynamic_type"]);
+                format!("import({specifier}{newlines})")
+            }
+            None => {
+                let specifier = with_type(&captures["specifier"], &captures["type"]);
+                format!("{} {specifier}{newlines}", &captures["keyword"])
+            }
+        };
+
+        rewritten.push_str(&code[last..all.start()]);
+        rewritten.push_str(&replacement);
+        last = all.end();
+    }
+
+    match last {
+        0 => Cow::Borrowed(code),
+        _ => {
+            rewritten.push_str(&code[last..]);
+            Cow::Owned(rewritten)
+        }
+    }
+}
+
+/// `"./icon.svg"` and `bytes` is `"./icon.svg?bytes"`.
+fn with_type(specifier: &str, ty: &str) -> String {
+    let (quote, specifier) = specifier.split_at(1);
+    let specifier = &specifier[..specifier.len() - 1];
+
+    format!("{quote}{specifier}?{ty}{quote}")
+}
+
+/// Byte ranges of strings, template text, comments and regular expressions,
+/// in order. `/` starts a regular expression where a value is expected.
+fn literals(code: &str) -> Vec<Range<usize>> {
+    const BEFORE_VALUE: &str = "return typeof instanceof in of new delete void throw case do else \
+        yield await";
+
+    let bytes = code.as_bytes();
+    let mut literals = Vec::new();
+    // the brace depth of each open `${`, to continue its template after `}`
+    let mut templates = Vec::new();
+    let mut depth = 0_usize;
+    let mut value_expected = true;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+
+        match bytes[i] {
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote && bytes[i] != b'\n' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i += 1;
+                value_expected = false;
+            }
+            b'`' => {
+                let (end, interpolation) = template(bytes, i + 1);
+                if interpolation {
+                    templates.push(depth);
+                    depth += 1;
+                }
+                i = end;
+                value_expected = interpolation;
+            }
+            b'}' if templates.last().is_some_and(|&open| open + 1 == depth) => {
+                templates.pop();
+                depth -= 1;
+
+                let (end, interpolation) = template(bytes, i + 1);
+                if interpolation {
+                    templates.push(depth);
+                    depth += 1;
+                }
+                i = end;
+                value_expected = interpolation;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = code[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2);
+            }
+            b'/' if value_expected => {
+                let mut class = false;
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    match bytes[i] {
+                        b'\\' => i += 1,
+                        b'[' => class = true,
+                        b']' => class = false,
+                        b'/' if !class => break,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                i += 1;
+                value_expected = false;
+            }
+            c if c.is_ascii_alphanumeric() || c == b'_' || c == b'$' => {
+                while i < bytes.len()
+                    && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || bytes[i] == b'$')
+                {
+                    i += 1;
+                }
+                let word = &code[start..i];
+                value_expected = BEFORE_VALUE
+                    .split_whitespace()
+                    .any(|keyword| keyword == word);
+                continue;
+            }
+            c => {
+                match c {
+                    b'{' => depth += 1,
+                    b'}' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                if !c.is_ascii_whitespace() {
+                    value_expected = !matches!(c, b')' | b']' | b'}');
+                }
+                i += 1;
+                continue;
+            }
+        }
+
+        literals.push(start..i.min(bytes.len()));
+    }
+
+    literals
+}
+
+/// Skips template text up to the closing backtick or a `${`, which is part
+/// of the text.
+fn template(bytes: &[u8], mut i: usize) -> (usize, bool) {
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => return (i + 1, false),
+            b'$' if bytes.get(i + 1) == Some(&b'{') => return (i + 2, true),
+            _ => i += 1,
+        }
+    }
+
+    (bytes.len(), false)
+}
+
+/// `assets/icon.svg?bytes` is the file `assets/icon.svg` and the type `bytes`.
+pub(crate) fn split_type(module_name: &str) -> (&str, Option<&str>) {
+    match module_name.split_once('?') {
+        Some((path, ty)) => (path, Some(ty)),
+        None => (module_name, None),
+    }
+}
+
+/// The module for a JSON, text or bytes import, `None` for code.
+///
+/// `.txt`, `.svg` and `.html` files are text by default, `?raw` is text like
+/// in Vite.
+pub(crate) fn module_source(
+    path: &str,
+    ty: Option<&str>,
+    contents: &[u8],
+) -> Option<Result<String, String>> {
+    let extension = path.rsplit_once('.').map(|(_, extension)| extension);
+
+    let source = match (ty, extension) {
+        (Some("json"), _) | (None, Some("json")) => serde_json::from_slice::<Value>(contents)
+            .map(|value| format!("export default {value};"))
+            .map_err(|err| format!("Module {path} is not valid JSON: {err}")),
+        (Some("text" | "raw"), _) | (None, Some("txt" | "svg" | "html")) => {
+            std::str::from_utf8(contents)
+                .map(|text| format!("export default {};", json!(text)))
+                .map_err(|_| format!("Module {path} is not valid UTF-8"))
+        }
+        (Some("bytes"), _) => {
+            // one char per byte, shorter than an array literal
+            let latin1 = contents
+                .iter()
+                .map(|&byte| byte as char)
+                .collect::<String>();
+
+            Ok(format!(
+                "const data = {};\n\
+                 const bytes = new Uint8Array(data.length);\n\
+                 for (let i = 0; i < data.length; i++) bytes[i] = data.charCodeAt(i);\n\
+                 export default bytes;",
+                json!(latin1)
+            ))
+        }
+        (Some(ty), _) => Err(format!(
+            "Module {path} has an unsupported import type '{ty}'"
+        )),
+        (None, _) => return None,
+    };
+
+    Some(source)
+}
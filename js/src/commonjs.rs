@@ -0,0 +1,134 @@
+use crate::context::{self, ContextConfig};
+use crate::module_source::ModuleSource;
+use crate::resolve::Kind;
+use quickjs_rusty::{Context, ExecutionError};
+use serde_json::{Value, json};
+
+/// Whether the loader has to wrap the file: `.cjs` files, and `.js` files of
+/// packages that aren't `"type": "module"`.
+///
+/// For Node, a package without `type` is CommonJS, but its `module` entry is
+/// ESM by convention, so those are told apart by their `import`/`export`s.
+pub(crate) fn is_commonjs(source: &dyn ModuleSource, path: &str, code: &str) -> bool {
+    let in_package = path.starts_with("node_modules/") || path.contains("/node_modules/");
+
+    match path.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("cjs") => true,
+        Some("js") if in_package => match package_type(source, path).as_deref() {
+            Some("module") => false,
+            Some("commonjs") => true,
+            _ => !has_esm_syntax(code),
+        },
+        _ => false,
+    }
+}
+
+/// `type` of the closest package.json.
+fn package_type(source: &dyn ModuleSource, path: &str) -> Option<String> {
+    let mut dir = path;
+
+    while let Some((parent, _)) = dir.rsplit_once('/') {
+        dir = parent;
+
+        if let Some(json) = source.read(&format!("{dir}/package.json")) {
+            let package = serde_json::from_slice::<Value>(&json).ok()?;
+            return package["type"].as_str().map(String::from);
+        }
+
+        if dir.ends_with("node_modules") {
+            break;
+        }
+    }
+
+    None
+}
+
+fn has_esm_syntax(code: &str) -> bool {
+    code.lines().map(str::trim_start).any(|line| {
+        ["import ", "import{", "export ", "export{"]
+            .iter()
+            .any(|keyword| line.starts_with(keyword))
+    })
+}
+
+/// The ES module the loader returns for a CommonJS file: `module.exports` is
+/// its default export.
+pub(crate) fn module_source(path: &str) -> String {
+    format!("export default __cjs.load({});", json!(path))
+}
+
+fn response(result: Result<Value, String>) -> String {
+    match result {
+        Ok(ok) => json!({ "ok": ok }),
+        Err(error) => json!({ "error": error }),
+    }
+    .to_string()
+}
+
+/// Installs `require` and the loader for CommonJS modules, see `js/commonjs.js`.
+///
+/// The config must outlive the context, like for the module loader.
+pub(crate) fn register(context: &Context, config: &ContextConfig) -> Result<(), ExecutionError> {
+    let config = config as *const ContextConfig as usize;
+    let js_context = unsafe { context.context_raw() } as usize;
+
+    context.add_callback("__cjsResolve", move |base: String, specifier: String| {
+        let config = unsafe { &*(config as *const ContextConfig) };
+
+        response(
+            context::normalize(config, Kind::Require, &base, &specifier)
+                .map(Value::String)
+                .map_err(|err| err.to_string()),
+        )
+    })?;
+
+    context.add_callback("__cjsLoad", move |path: String| {
+        let config = unsafe { &*(config as *const ContextConfig) };
+
+        response(load(config, js_context as *mut _, &path))
+    })?;
+
+    context.eval(include_str!("./js/commonjs.js"), false)?;
+
+    Ok(())
+}
+
+/// Defines the function wrapping a CommonJS file, or returns the text of a
+/// JSON file.
+fn load(
+    config: &ContextConfig,
+    js_context: *mut libquickjs_ng_sys::JSContext,
+    path: &str,
+) -> Result<Value, String> {
+    let source = config
+        .js_src
+        .as_ref()
+        .ok_or_else(|| format!("Module {path} not found, js_src_dir is not set"))?;
+
+    let contents = source
+        .read(path)
+        .ok_or_else(|| format!("Module {path} not found"))?;
+
+    let code =
+        std::str::from_utf8(&contents).map_err(|_| format!("Module {path} is not valid UTF-8"))?;
+
+    if path.ends_with(".json") {
+        return Ok(Value::String(code.to_string()));
+    }
+
+    if !is_commonjs(source.as_ref(), path, code) {
+        return Err(format!(
+            "Module {path} is an ES module, use import instead of require"
+        ));
+    }
+
+    // on the first line, so line numbers stay the same
+    let define = format!(
+        "__cjs.define({}, function (exports, require, module, __filename, __dirname) {{{code}\n}});",
+        json!(path)
+    );
+
+    context::eval_file(js_context, &define, path)
+        .map(|_| Value::Null)
+        .map_err(|err| context::execution_error(err).to_string())
+}
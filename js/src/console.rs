@@ -0,0 +1,127 @@
+use quickjs_rusty::{
+    OwnedJsValue,
+    console::{ConsoleBackend, Level},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsoleLevel {
+    Debug,
+    Log,
+    Info,
+    Warn,
+    Error,
+}
+
+impl From<Level> for ConsoleLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Trace | Level::Debug => Self::Debug,
+            Level::Log => Self::Log,
+            Level::Info => Self::Info,
+            Level::Warn => Self::Warn,
+            Level::Error => Self::Error,
+        }
+    }
+}
+
+/// One `console.*` call of a script.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConsoleRecord {
+    pub level: ConsoleLevel,
+    pub timestamp: SystemTime,
+    /// The arguments as JSON, values without a JSON form (functions, symbols, ...)
+    /// are kept as their string
+    pub values: Vec<Value>,
+}
+
+/// The execution a console call belongs to.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptInfo {
+    pub worker: usize,
+    /// Name of the compiled function, empty for inline code
+    pub script: String,
+    pub page: String,
+}
+
+#[derive(Default)]
+pub struct Console {
+    pub output: Arc<Mutex<String>>,
+    pub records: Arc<Mutex<Vec<ConsoleRecord>>>,
+    /// Emit tracing events instead of `log::debug!`
+    #[cfg(feature = "tracing")]
+    tracing: Option<ScriptInfo>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(feature = "tracing")]
+    pub fn with_tracing(mut self, info: ScriptInfo) -> Self {
+        self.tracing = Some(info);
+        self
+    }
+
+    fn emit(&self, level: ConsoleLevel, line: &str) {
+        #[cfg(feature = "tracing")]
+        if let Some(info) = &self.tracing {
+            macro_rules! event {
+                ($level:expr) => {
+                    tracing::event!(
+                        target: "js::console",
+                        $level,
+                        worker = info.worker,
+                        script = %info.script,
+                        page = %info.page,
+                        "{line}"
+                    )
+                };
+            }
+
+            match level {
+                ConsoleLevel::Debug => event!(tracing::Level::DEBUG),
+                ConsoleLevel::Log | ConsoleLevel::Info => event!(tracing::Level::INFO),
+                ConsoleLevel::Warn => event!(tracing::Level::WARN),
+                ConsoleLevel::Error => event!(tracing::Level::ERROR),
+            }
+            return;
+        }
+
+        log::debug!("{line}");
+    }
+}
+
+/// `console.js` writes `(line, ...values)`, the line already formatted.
+impl ConsoleBackend for Console {
+    fn log(&self, level: Level, mut values: Vec<OwnedJsValue>) {
+        let output_line = match values.is_empty() {
+            true => String::new(),
+            false => values.remove(0).js_to_string().unwrap_or_default(),
+        };
+        self.emit(level.into(), &output_line);
+        let mut output = self.output.lock().unwrap();
+        writeln!(output, "{}", output_line).unwrap();
+
+        // like `JSON.stringify`, values without JSON as their string
+        let values = values
+            .iter()
+            .map(|v| {
+                crate::context::to_json(v)
+                    .unwrap_or_else(|| Value::String(v.js_to_string().unwrap_or_default()))
+            })
+            .collect();
+
+        self.records.lock().unwrap().push(ConsoleRecord {
+            level: level.into(),
+            timestamp: SystemTime::now(),
+            values,
+        });
+    }
+}
@@ -1,34 +1,100 @@
-use include_dir::{Dir, DirEntry};
+use libquickjs_ng_sys as q;
 use quickjs_rusty::{
-    Context, JsCompiledFunction, OwnedJsValue,
-    console::{ConsoleBackend, Level},
-    serde::to_js,
+    Context, ExecutionError, OwnedJsValue, PromiseState, serde::to_js, utils::make_cstring,
 };
+use std::cell::{Cell, RefCell};
 use std::path::{Component, PathBuf};
+use std::time::{Duration, Instant};
 
 use serde::Serialize;
-use serde_json::json;
-use std::sync::{Arc, Mutex, OnceLock};
-use std::{collections::HashMap, fmt::Write};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-static JS_SRC_DIR: OnceLock<Dir<'static>> = OnceLock::new();
+/// Upper bound for blocking on timers or host functions, so interrupts are noticed quickly.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
 
 use super::*;
-
-pub struct ContextConfig<'a> {
-    pub js_src: Option<Dir<'a>>,
+use crate::assets;
+use crate::commonjs;
+use crate::console::Console;
+use crate::module_source::ModuleSource;
+use crate::resolve::{self, ImportMap};
+use crate::source_map::SourceMaps;
+
+#[derive(Clone)]
+pub struct ContextConfig {
+    pub js_src: Option<Arc<dyn ModuleSource>>,
+    /// Aliases applied to imports before resolving them in `js_src`
+    pub import_map: Option<ImportMap>,
+    /// Heap limit of the QuickJS runtime in bytes
+    pub memory_limit: Option<usize>,
+    /// Maximum stack size of the QuickJS runtime in bytes
+    pub max_stack_size: Option<usize>,
+    /// Allocated bytes that trigger the next garbage collection
+    pub gc_threshold: Option<usize>,
+    pub host_functions: HostFunctions,
+    /// Filled while transpiling, used to remap stack traces
+    pub source_maps: SourceMaps,
+    /// Runs async host functions
+    pub tokio_handle: Option<tokio::runtime::Handle>,
 }
 
-impl<'a> Default for ContextConfig<'a> {
+impl Default for ContextConfig {
     fn default() -> Self {
-        Self { js_src: None }
+        Self {
+            js_src: None,
+            import_map: None,
+            memory_limit: None,
+            max_stack_size: None,
+            gc_threshold: None,
+            host_functions: HostFunctions::default(),
+            source_maps: SourceMaps::default(),
+            tokio_handle: None,
+        }
     }
 }
 
 #[derive(Debug)]
 pub enum Function {
     Code(String),
-    Compiled(JsCompiledFunction),
+    Compiled(CompiledFunction),
+}
+
+/// Script bytecode. Unlike `JsCompiledFunction` it gives access to the value,
+/// so `eval` can run it and still inspect the thrown exception.
+#[derive(Clone, Debug)]
+pub struct CompiledFunction(OwnedJsValue);
+
+impl CompiledFunction {
+    pub fn compile(
+        js_context: *mut q::JSContext,
+        code: &str,
+        name: &str,
+    ) -> Result<Self, ExecutionError> {
+        let code_c = make_cstring(code)?;
+        let name_c = make_cstring(name)?;
+
+        // unlike `quickjs_rusty::compile::compile`, keeps the SyntaxError and its location
+        let value = check_exception(OwnedJsValue::new(js_context, unsafe {
+            q::JS_Eval(
+                js_context,
+                code_c.as_ptr(),
+                code.len(),
+                name_c.as_ptr(),
+                q::JS_EVAL_FLAG_COMPILE_ONLY as i32,
+            )
+        }))?;
+
+        if !value.is_compiled_function() {
+            return Err(ExecutionError::Internal(format!(
+                "{name} did not compile to a function"
+            )));
+        }
+
+        Ok(Self(value))
+    }
 }
 
 impl From<&str> for Function {
@@ -37,60 +103,136 @@ impl From<&str> for Function {
     }
 }
 
-pub struct Console {
-    pub output: Arc<Mutex<String>>,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interrupted {
+    Timeout,
+    Cancelled,
+    Aborted,
+}
+
+/// Execution deadline and cancellation flags checked by the QuickJS interrupt handler.
+///
+/// A worker owns one `Interrupt` for the lifetime of its context and arms it
+/// before every script.
+pub struct Interrupt {
+    deadline: Cell<Option<Instant>>,
+    cancelled: RefCell<Option<Arc<AtomicBool>>>,
+    /// Interrupts any script, set when the runtime is forced to shut down
+    aborted: Arc<AtomicBool>,
+    triggered: Cell<Option<Interrupted>>,
 }
 
-impl Console {
-    pub fn new() -> Self {
+impl Interrupt {
+    pub fn new(aborted: Arc<AtomicBool>) -> Self {
         Self {
-            output: Arc::new(Mutex::new(String::from(""))),
+            deadline: Cell::new(None),
+            cancelled: RefCell::new(None),
+            aborted,
+            triggered: Cell::new(None),
         }
     }
-}
 
-struct ResolveContext {}
+    pub fn arm(&self, timeout: Option<Duration>, cancelled: Option<Arc<AtomicBool>>) {
+        self.deadline
+            .set(timeout.map(|timeout| Instant::now() + timeout));
+        self.cancelled.replace(cancelled);
+        self.triggered.set(None);
+    }
 
-impl ConsoleBackend for Console {
-    fn log(&self, _level: Level, values: Vec<OwnedJsValue>) {
-        let output_line = values
-            .into_iter()
-            .map(|v| v.js_to_string().unwrap_or_default())
-            .collect::<Vec<_>>()
-            .join(", ");
-        log::debug!("{output_line}");
-        let mut output = self.output.lock().unwrap();
-        writeln!(output, "{}", output_line).unwrap();
+    /// Returns the reason if the script was interrupted since the last `arm`.
+    pub fn disarm(&self) -> Option<Interrupted> {
+        self.deadline.set(None);
+        self.cancelled.replace(None);
+        self.triggered.take()
     }
-}
 
-pub fn init_module_loader(config: ContextConfig<'static>) -> Option<&'static Dir<'static>> {
-    if let Some(dir) = config.js_src {
-        return Some(JS_SRC_DIR.get_or_init(|| dir));
+    /// Records the reason if the script has to stop.
+    fn poll(&self) -> bool {
+        match self.check() {
+            Some(reason) => {
+                self.triggered.set(Some(reason));
+                true
+            }
+            None => false,
+        }
     }
 
-    None
+    fn check(&self) -> Option<Interrupted> {
+        if self.aborted.load(Ordering::Relaxed) {
+            return Some(Interrupted::Aborted);
+        }
+
+        if let Some(cancelled) = self.cancelled.borrow().as_ref()
+            && cancelled.load(Ordering::Relaxed)
+        {
+            return Some(Interrupted::Cancelled);
+        }
+
+        match self.deadline.get() {
+            Some(deadline) if Instant::now() >= deadline => Some(Interrupted::Timeout),
+            _ => None,
+        }
+    }
+}
+
+unsafe extern "C" fn interrupt_handler(
+    _rt: *mut q::JSRuntime,
+    opaque: *mut std::ffi::c_void,
+) -> std::ffi::c_int {
+    let interrupt = unsafe { &*(opaque as *const Interrupt) };
+
+    interrupt.poll() as std::ffi::c_int
 }
 
-pub fn get_js_dir() -> Option<&'static Dir<'static>> {
-    JS_SRC_DIR.get()
+/// The interrupt must outlive the context.
+pub fn set_interrupt_handler(context: &Context, interrupt: &Interrupt) {
+    unsafe {
+        let rt = q::JS_GetRuntime(context.context_raw());
+        q::JS_SetInterruptHandler(rt, Some(interrupt_handler), interrupt as *const _ as *mut _);
+    }
 }
 
-pub fn init() -> Result<Context, Error> {
-    let context = Context::builder().console(Console::new()).build()?;
+/// The config must outlive the context, the module loader refers to it.
+pub fn init(config: &ContextConfig) -> Result<Context, Error> {
+    let mut builder = Context::builder().console(Console::new());
+
+    if let Some(memory_limit) = config.memory_limit {
+        builder = builder.memory_limit(memory_limit);
+    }
+
+    let context = builder.build()?;
 
     let js_context = unsafe { context.context_raw() };
 
+    unsafe {
+        let rt = q::JS_GetRuntime(js_context);
+        if let Some(max_stack_size) = config.max_stack_size {
+            q::JS_SetMaxStackSize(rt, max_stack_size);
+        }
+        if let Some(gc_threshold) = config.gc_threshold {
+            q::JS_SetGCThreshold(rt, gc_threshold);
+        }
+    }
+
     let ctx = to_js(js_context, &json!({"name": "script"}))?;
 
     context.set_global("ctx", ctx)?;
 
-    let opaque = ResolveContext {};
+    context.eval(include_str!("./js/timers.js"), false)?;
+    context.eval(include_str!("./js/console.js"), false)?;
+
+    host::register(
+        &context,
+        &config.host_functions,
+        config.tokio_handle.clone(),
+    )?;
+
+    commonjs::register(&context, config)?;
 
     context.set_module_loader(
         Box::new(module_loader),
         Some(Box::new(module_normalize)),
-        &opaque as *const _ as *mut _,
+        config as *const ContextConfig as *mut _,
     );
 
     Ok(context)
@@ -99,31 +241,44 @@ pub fn init() -> Result<Context, Error> {
 pub fn compile_functions(
     context: &Context,
     functions: HashMap<String, String>,
-) -> Result<HashMap<String, JsCompiledFunction>, Error> {
-    let js_context = unsafe { context.context_raw() };
-
-    let mut compiled_fns = HashMap::new();
+    source_maps: &SourceMaps,
+) -> Result<HashMap<String, CompiledFunction>, Error> {
+    functions
+        .into_iter()
+        .map(|(name, code)| {
+            let compiled_fn = compile_function(context, &name, code, source_maps)?;
+            Ok((name, compiled_fn))
+        })
+        .collect()
+}
 
-    #[allow(unused_mut)]
-    for (name, mut code) in functions.into_iter() {
-        if name.ends_with(".ts") {
-            #[cfg(feature = "transpiling")]
-            {
-                code = transpile_sript(&code, None)?;
-            }
+pub fn compile_function(
+    context: &Context,
+    name: &str,
+    #[allow(unused_mut)] mut code: String,
+    #[allow(unused_variables)] source_maps: &SourceMaps,
+) -> Result<CompiledFunction, Error> {
+    let js_context = unsafe { context.context_raw() };
 
-            #[cfg(not(feature = "transpiling"))]
-            {
-                panic!("TypeScript is not supported. Enable the 'ts' feature to use it.");
+    if name.ends_with(".ts") {
+        #[cfg(feature = "transpiling")]
+        {
+            let transpiled = transpile_script_source(&code, None)?;
+            if let Some(source_map) = &transpiled.source_map {
+                source_maps.insert(name, source_map);
             }
+            code = transpiled.text;
         }
-        let compiled_fn = quickjs_rusty::compile::compile(js_context, &code, &name)?
-            .try_into_compiled_function()?;
 
-        compiled_fns.insert(name, compiled_fn);
+        #[cfg(not(feature = "transpiling"))]
+        {
+            panic!("TypeScript is not supported. Enable the 'ts' feature to use it.");
+        }
     }
 
-    Ok(compiled_fns)
+    let code = assets::rewrite_import_attributes(&code);
+
+    CompiledFunction::compile(js_context, &code, name).map_err(execution_error)
 }
 
 pub fn module_loader(module_name: &str, opaque: *mut std::ffi::c_void) -> anyhow::Result<String> {
@@ -134,33 +289,48 @@ pub fn module_loader(module_name: &str, opaque: *mut std::ffi::c_void) -> anyhow
         return Ok(include_str!("./js/jsx.js").into());
     }
 
-    let dir = JS_SRC_DIR
-        .get()
-        .ok_or_else(|| anyhow::anyhow!("JS_SRC_DIR not initialized"))?;
+    let config = unsafe { &*(opaque as *const ContextConfig) };
 
-    let module = dir.get_entry(module_name);
+    if let Some(module) = module_name.strip_prefix("host:") {
+        return config
+            .host_functions
+            .module_source(module)
+            .ok_or_else(|| anyhow::anyhow!("Host module {module} not found"));
+    }
 
-    let file = match module {
-        // try to get barrel file
-        // TODO: handle .ts, .jsx, .tsx
-        Some(DirEntry::Dir(dir)) => {
-            if let Some(index) = dir.get_file("index.js") {
-                Ok(index)
-            } else {
-                Err(anyhow::anyhow!("Module {module_name} not found"))
-            }
-        }
-        Some(DirEntry::File(file)) => Ok(file),
-        None => Err(anyhow::anyhow!("Module {module_name} not found")),
-    }?;
+    let modules = config
+        .js_src
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Module {module_name} not found, js_src_dir is not set"))?;
 
-    let source = file
-        .contents_utf8()
-        .ok_or_else(|| anyhow::anyhow!("Module {module_name} is not valid UTF-8"))?;
+    // already resolved to a file by `module_normalize`
+    let (path, ty) = assets::split_type(module_name);
 
-    if !module_name.ends_with(".js") {
+    let contents = modules
+        .read(path)
+        .ok_or_else(|| anyhow::anyhow!("Module {module_name} not found"))?;
+
+    if let Some(module) = assets::module_source(path, ty, &contents) {
+        return module.map_err(|err| anyhow::anyhow!(err));
+    }
+
+    let source = std::str::from_utf8(&contents)
+        .map_err(|_| anyhow::anyhow!("Module {module_name} is not valid UTF-8"))?;
+
+    if commonjs::is_commonjs(modules.as_ref(), path, source) {
+        return Ok(commonjs::module_source(path));
+    }
+
+    if !(path.ends_with(".js") || path.ends_with(".mjs")) {
         #[cfg(feature = "transpiling")]
-        return transpile_module(&file.path(), source).map_err(|e| anyhow::anyhow!(e));
+        return transpile_module_source(std::path::Path::new(path), source)
+            .map(|transpiled| {
+                if let Some(source_map) = &transpiled.source_map {
+                    config.source_maps.insert(module_name, source_map);
+                }
+                assets::rewrite_import_attributes(&transpiled.text).into_owned()
+            })
+            .map_err(|e| anyhow::anyhow!(e));
 
         #[cfg(not(feature = "transpiling"))]
         return Err(anyhow::anyhow!(
@@ -168,7 +338,7 @@ pub fn module_loader(module_name: &str, opaque: *mut std::ffi::c_void) -> anyhow
         ));
     }
 
-    Ok(source.to_string())
+    Ok(assets::rewrite_import_attributes(source).into_owned())
 }
 
 pub fn module_normalize(
@@ -176,6 +346,21 @@ pub fn module_normalize(
     module_name: &str,
     opaque: *mut std::ffi::c_void,
 ) -> anyhow::Result<String> {
+    let config = unsafe { &*(opaque as *const ContextConfig) };
+
+    normalize(config, resolve::Kind::Import, module_base_name, module_name)
+}
+
+/// Resolves an import or `require` to the name of the module.
+pub(crate) fn normalize(
+    config: &ContextConfig,
+    kind: resolve::Kind,
+    module_base_name: &str,
+    module_name: &str,
+) -> anyhow::Result<String> {
+    // `?bytes` of an import with attributes, kept for the loader
+    let (module_name, ty) = assets::split_type(module_name);
+
     let normalized_module_name = if module_name.starts_with("./") || module_name.starts_with("../")
     {
         let module_path = std::path::Path::new(module_base_name)
@@ -212,6 +397,32 @@ pub fn module_normalize(
         module_name.to_string()
     };
 
+    // built-in and host modules are not files
+    let normalized_module_name = match &config.js_src {
+        Some(modules)
+            if normalized_module_name != "/jsx-runtime"
+                && !normalized_module_name.starts_with("host:") =>
+        {
+            resolve::resolve(
+                modules.as_ref(),
+                config.import_map.as_ref(),
+                kind,
+                module_base_name,
+                module_name,
+                &normalized_module_name,
+            )
+            .map_err(|err| {
+                anyhow::anyhow!("Module '{module_name}' imported from '{module_base_name}': {err}")
+            })?
+        }
+        _ => normalized_module_name,
+    };
+
+    let normalized_module_name = match ty {
+        Some(ty) => format!("{normalized_module_name}?{ty}"),
+        None => normalized_module_name,
+    };
+
     log::trace!(
         "module_normalize: '{}' + '{}' -> '{}'",
         module_base_name,
@@ -222,16 +433,21 @@ pub fn module_normalize(
     Ok(normalized_module_name)
 }
 
+/// With `json`, the output is the result as `JSON.stringify` converts it,
+/// `null` if it has no JSON representation.
 pub fn eval<Args>(
     context: &Context,
+    interrupt: &Interrupt,
+    console: Console,
     args: Option<Args>,
     source: Function,
+    json: bool,
 ) -> Result<ScriptOutput, Error>
 where
     Args: Serialize,
 {
-    let console = Console::new();
     let output = console.output.clone();
+    let records = console.records.clone();
 
     context.set_console(Box::new(console))?;
 
@@ -240,23 +456,264 @@ where
     let args = to_js(js_context, &args)?;
     context.set_global("args", args)?;
 
+    // timers and host calls left behind by an interrupted script,
+    // the console API on top of the fresh `console` from `set_console`
+    context.eval(
+        "__clearTimers(); __host.clear(); __console.install()",
+        false,
+    )?;
+
     let result = match source {
-        Function::Code(code) => context.eval(&code, false)?,
-        Function::Compiled(compiled_fn) => compiled_fn.eval()?,
+        Function::Code(code) => eval_script(context, &assets::rewrite_import_attributes(&code)),
+        Function::Compiled(compiled_fn) => eval_compiled(&compiled_fn),
+    }
+    .and_then(|result| {
+        run_event_loop(context, interrupt)?;
+        resolve_promise(result)
+    })
+    .map_err(execution_error)?;
+
+    let result = match json {
+        true => stringify(&result).unwrap_or_else(|| {
+            log::debug!("script result is not representable as JSON");
+            "null".into()
+        }),
+        false => result.js_to_string()?,
     };
-    let result = result.js_to_string()?;
 
     let output = output.lock().unwrap();
     let console_output = output.clone();
+    let console = std::mem::take(&mut *records.lock().unwrap());
 
     Ok(ScriptOutput {
         output: result,
         console_output,
+        console,
     })
 }
 
+/// Runs pending jobs, timers and async host functions until there is nothing left to do.
+///
+/// Waiting is bounded by the interrupt, so a script with a pending
+/// `setInterval` still runs into its timeout.
+fn run_event_loop(context: &Context, interrupt: &Interrupt) -> Result<(), ExecutionError> {
+    let js_context = unsafe { context.context_raw() };
+
+    loop {
+        run_pending_jobs(js_context)?;
+
+        if interrupt.poll() {
+            return Err(ExecutionError::Internal("interrupted".into()));
+        }
+
+        let next_timer = eval_script(context, "__runTimers()")?.to_int()?;
+        if next_timer == 0 {
+            continue;
+        }
+
+        let wait = match next_timer {
+            next if next < 0 => POLL_INTERVAL,
+            next => Duration::from_millis(next as u64).min(POLL_INTERVAL),
+        };
+
+        if eval_script(context, "__host.pending()")?.to_int()? > 0 {
+            eval_script(context, &format!("__host.wait({})", wait.as_millis()))?;
+        } else if next_timer < 0 {
+            return Ok(());
+        } else {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// Runs promise jobs until the queue is empty.
+fn run_pending_jobs(js_context: *mut q::JSContext) -> Result<(), ExecutionError> {
+    let rt = unsafe { q::JS_GetRuntime(js_context) };
+
+    loop {
+        let mut job_context = std::ptr::null_mut();
+        let executed = unsafe { q::JS_ExecutePendingJob(rt, &mut job_context) };
+
+        if executed < 0 {
+            return Err(take_exception(job_context));
+        }
+
+        if executed == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// If the value is a promise, returns its result. A rejection is returned as an exception.
+///
+/// Expects the event loop to have run, so a pending promise will never settle.
+fn resolve_promise(value: OwnedJsValue) -> Result<OwnedJsValue, ExecutionError> {
+    if !value.is_promise() {
+        return Ok(value);
+    }
+
+    let promise = value.try_into_promise()?;
+
+    match promise.state() {
+        PromiseState::Fulfilled => Ok(promise.result()),
+        PromiseState::Rejected => Err(ExecutionError::Exception(promise.result())),
+        PromiseState::Pending => Err(ExecutionError::Internal(
+            "promise is still pending, but there are no jobs or timers left to run".into(),
+        )),
+    }
+}
+
+/// Like `Context::eval`, but keeps the thrown value instead of turning it into a string.
+fn eval_script(context: &Context, code: &str) -> Result<OwnedJsValue, ExecutionError> {
+    eval_file(unsafe { context.context_raw() }, code, "script.js")
+}
+
+/// Evaluates global code as `filename`, which shows up in stack traces.
+pub(crate) fn eval_file(
+    js_context: *mut q::JSContext,
+    code: &str,
+    filename: &str,
+) -> Result<OwnedJsValue, ExecutionError> {
+    let code_c = make_cstring(code)?;
+    let filename_c = make_cstring(filename)?;
+
+    let value = unsafe {
+        q::JS_Eval(
+            js_context,
+            code_c.as_ptr(),
+            code.len(),
+            filename_c.as_ptr(),
+            q::JS_EVAL_TYPE_GLOBAL as i32,
+        )
+    };
+
+    check_exception(OwnedJsValue::new(js_context, value))
+}
+
+/// Like `Context::eval_module`, but keeps the thrown value, also of a module
+/// that throws while it is evaluated.
+pub fn eval_module(context: &Context, code: &str) -> Result<OwnedJsValue, ExecutionError> {
+    let js_context = unsafe { context.context_raw() };
+    let code_c = make_cstring(code)?;
+    let filename_c = make_cstring("module.js")?;
+
+    let value = unsafe {
+        q::JS_Eval(
+            js_context,
+            code_c.as_ptr(),
+            code.len(),
+            filename_c.as_ptr(),
+            q::JS_EVAL_TYPE_MODULE as i32,
+        )
+    };
+
+    let value = check_exception(OwnedJsValue::new(js_context, value))?;
+
+    // the module's promise is rejected if it throws at the top level
+    run_pending_jobs(js_context)?;
+
+    if value.is_promise()
+        && let promise = value.clone().try_into_promise()?
+        && matches!(promise.state(), PromiseState::Rejected)
+    {
+        return Err(ExecutionError::Exception(promise.result()));
+    }
+
+    Ok(value)
+}
+
+/// Like `JsCompiledFunction::eval`, but keeps the thrown value.
+fn eval_compiled(function: &CompiledFunction) -> Result<OwnedJsValue, ExecutionError> {
+    let js_context = function.0.context();
+
+    // JS_EvalFunction takes ownership of the function
+    let value = unsafe { q::JS_EvalFunction(js_context, function.0.clone().extract()) };
+
+    check_exception(OwnedJsValue::new(js_context, value))
+}
+
+fn check_exception(value: OwnedJsValue) -> Result<OwnedJsValue, ExecutionError> {
+    if value.is_exception() {
+        Err(take_exception(value.context()))
+    } else {
+        Ok(value)
+    }
+}
+
+fn take_exception(js_context: *mut q::JSContext) -> ExecutionError {
+    let value = OwnedJsValue::new(js_context, unsafe { q::JS_GetException(js_context) });
+
+    match value.js_to_string() {
+        Ok(message) if message == "InternalError: out of memory" => ExecutionError::OutOfMemory,
+        _ => ExecutionError::Exception(value),
+    }
+}
+
+/// Separates resource limit violations from regular JS exceptions, which are
+/// converted to `JsError` before the value is dropped with its context.
+pub fn execution_error(err: ExecutionError) -> Error {
+    match err {
+        ExecutionError::OutOfMemory => Error::OutOfMemory,
+        // QuickJS throws `null` if it can't even allocate the "out of memory" error
+        ExecutionError::Exception(ref value) if value.is_null() && heap_exhausted(value) => {
+            Error::OutOfMemory
+        }
+        ExecutionError::Exception(ref value) => {
+            let error = JsError::from_exception(value);
+
+            // a script can throw the same message, only the engine's error counts
+            if error.name == "RangeError" && error.message == "Maximum call stack size exceeded" {
+                Error::StackOverflow
+            } else {
+                Error::Js(error)
+            }
+        }
+        err => Error::Execution(err),
+    }
+}
+
+/// Converts like `JSON.stringify`: `undefined` and functions in arrays become
+/// `null`, in objects they are left out. `None` if there is no JSON for the
+/// value, e.g. `undefined`, a function, a BigInt or a cycle.
+pub(crate) fn to_json(value: &OwnedJsValue) -> Option<Value> {
+    serde_json::from_str(&stringify(value)?).ok()
+}
+
+/// `JSON.stringify`, `None` where it returns `undefined` or throws.
+fn stringify(value: &OwnedJsValue) -> Option<String> {
+    match value.to_json_string(0) {
+        Ok(json) => Some(json),
+        Err(_) => {
+            // the TypeError of a BigInt or cycle
+            let js_context = value.context();
+            if unsafe { q::JS_HasException(js_context) } {
+                drop(take_exception(js_context));
+            }
+            None
+        }
+    }
+}
+
+fn heap_exhausted(value: &OwnedJsValue) -> bool {
+    let usage = unsafe {
+        let mut usage = std::mem::zeroed::<q::JSMemoryUsage>();
+        q::JS_ComputeMemoryUsage(q::JS_GetRuntime(value.context()), &mut usage);
+        usage
+    };
+
+    usage.malloc_limit > 0 && usage.malloc_size >= usage.malloc_limit / 10 * 9
+}
+
 #[cfg(feature = "transpiling")]
 pub fn transpile_sript(source: &str, ty: Option<deno_ast::MediaType>) -> Result<String, Error> {
+    Ok(transpile_script_source(source, ty)?.text)
+}
+
+#[cfg(feature = "transpiling")]
+fn transpile_script_source(
+    source: &str,
+    ty: Option<deno_ast::MediaType>,
+) -> Result<deno_ast::EmittedSourceText, Error> {
     let parsed = deno_ast::parse_script(deno_ast::ParseParams {
         specifier: deno_ast::ModuleSpecifier::parse("file://script.ts").unwrap(),
         text: source.into(),
@@ -284,7 +741,7 @@ pub fn transpile_sript(source: &str, ty: Option<deno_ast::MediaType>) -> Result<
         )?
         .into_source();
 
-    Ok(res.text)
+    Ok(res)
 }
 
 #[cfg(feature = "transpiling")]
@@ -292,6 +749,14 @@ pub fn transpile_module(
     path: &std::path::Path,
     source: impl Into<String>,
 ) -> Result<String, Error> {
+    Ok(transpile_module_source(path, source)?.text)
+}
+
+#[cfg(feature = "transpiling")]
+fn transpile_module_source(
+    path: &std::path::Path,
+    source: impl Into<String>,
+) -> Result<deno_ast::EmittedSourceText, Error> {
     let file_url = format!("file://{}", path.display());
 
     let media_type = deno_ast::MediaType::from_path(path);
@@ -324,12 +789,12 @@ pub fn transpile_module(
         )?
         .into_source();
 
-    Ok(res.text)
+    Ok(res)
 }
 
 #[cfg(test)]
 mod tests {
-    use include_dir::File;
+    use include_dir::{Dir, DirEntry, File};
     use quickjs_rusty::{module_loader::ModuleLoader, utils::make_cstring};
     use serde_json::Value;
 
@@ -352,13 +817,22 @@ mod tests {
             Dir::new("src", &files)
         };
 
-        let dir = init_module_loader(ContextConfig {
-            js_src: Some(js_src),
-        });
-
-        let ctx = init().unwrap();
+        let config = ContextConfig {
+            js_src: Some(Arc::new(js_src)),
+            ..Default::default()
+        };
+        let ctx = init(&config).unwrap();
         ctx.eval_module("import './lib.js';", false).unwrap();
-        let res = context::eval(&ctx, Some(Value::Null), "globalThis.hello".into()).unwrap();
+        let interrupt = Interrupt::new(Arc::default());
+        let res = context::eval(
+            &ctx,
+            &interrupt,
+            Console::new(),
+            Some(Value::Null),
+            "globalThis.hello".into(),
+            false,
+        )
+        .unwrap();
 
         assert_eq!(res.output, "hello");
     }
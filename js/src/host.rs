@@ -0,0 +1,325 @@
+use quickjs_rusty::{Context, ExecutionError};
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::panic::RefUnwindSafe;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Duration;
+
+type HostResult = Result<Value, String>;
+type HostFuture = Pin<Box<dyn Future<Output = HostResult> + Send>>;
+
+#[derive(Clone)]
+enum HostFunction {
+    Sync(Arc<dyn Fn(Value) -> HostResult + Send + Sync>),
+    Async(Arc<dyn Fn(Value) -> HostFuture + Send + Sync>),
+}
+
+#[derive(Clone)]
+struct Entry {
+    module: Option<String>,
+    name: String,
+    function: HostFunction,
+}
+
+impl Entry {
+    fn key(&self) -> String {
+        match &self.module {
+            Some(module) => format!("host:{module}/{}", self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// Rust functions callable from JS, either as globals or exported from a
+/// `host:<module>` module.
+///
+/// The JS arguments are deserialized as an array, so a function called as
+/// `add(1, 2)` takes an `(i32, i32)` tuple and `get(1)` takes `(u64,)`.
+/// Errors are thrown as JS `Error`s, async functions return a promise.
+/// Names must be JS identifiers, anything else panics when registered.
+///
+/// ```ignore
+/// let host = HostFunctions::new()
+///     .global("add", |(a, b): (i32, i32)| Ok::<_, Infallible>(a + b))
+///     .export_async("app", "user", |(id,): (u64,)| async move { db::user(id).await });
+/// ```
+#[derive(Clone, Default)]
+pub struct HostFunctions {
+    entries: Arc<Vec<Entry>>,
+}
+
+impl HostFunctions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a global function.
+    pub fn global<A, R, E, F>(self, name: &str, f: F) -> Self
+    where
+        A: DeserializeOwned,
+        R: Serialize,
+        E: Display,
+        F: Fn(A) -> Result<R, E> + Send + Sync + 'static,
+    {
+        self.add(None, name, sync_function(f))
+    }
+
+    /// Registers a global function returning a promise.
+    pub fn global_async<A, R, E, F, Fut>(self, name: &str, f: F) -> Self
+    where
+        A: DeserializeOwned,
+        R: Serialize,
+        E: Display,
+        F: Fn(A) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R, E>> + Send + 'static,
+    {
+        self.add(None, name, async_function(f))
+    }
+
+    /// Exports a function from `host:<module>`.
+    pub fn export<A, R, E, F>(self, module: &str, name: &str, f: F) -> Self
+    where
+        A: DeserializeOwned,
+        R: Serialize,
+        E: Display,
+        F: Fn(A) -> Result<R, E> + Send + Sync + 'static,
+    {
+        self.add(Some(module), name, sync_function(f))
+    }
+
+    /// Exports a function returning a promise from `host:<module>`.
+    pub fn export_async<A, R, E, F, Fut>(self, module: &str, name: &str, f: F) -> Self
+    where
+        A: DeserializeOwned,
+        R: Serialize,
+        E: Display,
+        F: Fn(A) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R, E>> + Send + 'static,
+    {
+        self.add(Some(module), name, async_function(f))
+    }
+
+    fn add(mut self, module: Option<&str>, name: &str, function: HostFunction) -> Self {
+        assert!(
+            is_identifier(name),
+            "host function name '{name}' is not a valid JS identifier"
+        );
+
+        let entries = Arc::make_mut(&mut self.entries);
+        let module = module.map(str::to_string);
+
+        entries.retain(|entry| entry.module != module || entry.name != name);
+        entries.push(Entry {
+            module,
+            name: name.into(),
+            function,
+        });
+
+        self
+    }
+
+    /// Source of the `host:<module>` module, if anything is exported from it.
+    pub(crate) fn module_source(&self, module: &str) -> Option<String> {
+        let mut source = format!(
+            "const __module = globalThis.__host.modules[{}];\n",
+            json!(module)
+        );
+        let mut found = false;
+
+        for entry in self.entries.iter() {
+            if entry.module.as_deref() == Some(module) {
+                found = true;
+                source.push_str(&format!(
+                    "export const {} = __module[{}];\n",
+                    entry.name,
+                    json!(entry.name)
+                ));
+            }
+        }
+
+        found.then_some(source)
+    }
+}
+
+/// `export const {name}` of `module_source` has to compile.
+fn is_identifier(name: &str) -> bool {
+    const RESERVED: &str = "await break case catch class const continue debugger default \
+        delete do else enum export extends false finally for function if implements import in \
+        instanceof interface let new null package private protected public return static super \
+        switch this throw true try typeof var void while with yield __module";
+
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        && !RESERVED.split_whitespace().any(|word| word == name)
+}
+
+fn sync_function<A, R, E, F>(f: F) -> HostFunction
+where
+    A: DeserializeOwned,
+    R: Serialize,
+    E: Display,
+    F: Fn(A) -> Result<R, E> + Send + Sync + 'static,
+{
+    HostFunction::Sync(Arc::new(move |args| {
+        let result = f(arguments(args)?).map_err(|e| e.to_string())?;
+        serde_json::to_value(result).map_err(|e| e.to_string())
+    }))
+}
+
+fn async_function<A, R, E, F, Fut>(f: F) -> HostFunction
+where
+    A: DeserializeOwned,
+    R: Serialize,
+    E: Display,
+    F: Fn(A) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<R, E>> + Send + 'static,
+{
+    HostFunction::Async(Arc::new(move |args| {
+        let future = arguments(args).map(&f);
+
+        Box::pin(async move {
+            let result = future?.await;
+            serde_json::to_value(result.map_err(|e| e.to_string())?).map_err(|e| e.to_string())
+        })
+    }))
+}
+
+/// Deserializes the JS arguments, a call without arguments also matches `()`.
+fn arguments<A: DeserializeOwned>(args: Value) -> Result<A, String> {
+    match args {
+        Value::Array(args) if args.is_empty() => serde_json::from_value(Value::Null)
+            .or_else(|_| serde_json::from_value(Value::Array(args))),
+        args => serde_json::from_value(args),
+    }
+    .map_err(|e| e.to_string())
+}
+
+fn response(result: HostResult) -> Value {
+    match result {
+        Ok(value) => json!({ "ok": value }),
+        Err(message) => json!({ "error": message }),
+    }
+}
+
+/// Per context state behind the `__hostCall`, `__hostSpawn` and `__hostWait` callbacks.
+struct HostCalls {
+    functions: HashMap<String, HostFunction>,
+    handle: Option<tokio::runtime::Handle>,
+    sender: mpsc::Sender<(i32, HostResult)>,
+    receiver: Mutex<mpsc::Receiver<(i32, HostResult)>>,
+}
+
+// the callbacks never observe a half-updated state, a panic only aborts the call
+impl RefUnwindSafe for HostCalls {}
+
+impl HostCalls {
+    fn call(&self, key: &str, args: &str) -> HostResult {
+        match self.functions.get(key) {
+            Some(HostFunction::Sync(f)) => {
+                f(serde_json::from_str(args).map_err(|e| e.to_string())?)
+            }
+            _ => Err(format!("host function {key} not found")),
+        }
+    }
+
+    /// Starts an async function, its result is picked up by `wait`.
+    fn spawn(&self, id: i32, key: String, args: &str) -> HostResult {
+        let Some(HostFunction::Async(f)) = self.functions.get(&key) else {
+            return Err(format!("host function {key} not found"));
+        };
+
+        let Some(handle) = &self.handle else {
+            return Err("async host functions require a tokio runtime".into());
+        };
+
+        let task = handle.spawn(f(serde_json::from_str(args).map_err(|e| e.to_string())?));
+        let sender = self.sender.clone();
+
+        handle.spawn(async move {
+            let result = task
+                .await
+                .unwrap_or_else(|_| Err(format!("host function {key} panicked")));
+            _ = sender.send((id, result));
+        });
+
+        Ok(Value::Null)
+    }
+
+    /// Blocks up to `timeout` for the first completed call, then collects the others.
+    fn wait(&self, timeout: Duration) -> Vec<(i32, HostResult)> {
+        let receiver = self.receiver.lock().unwrap();
+
+        match receiver.recv_timeout(timeout) {
+            Ok(first) => std::iter::once(first).chain(receiver.try_iter()).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// Installs the host functions and their JS glue in the context.
+///
+/// Async functions are spawned on `handle`, the worker's event loop waits
+/// for them through `__host.wait`.
+pub(crate) fn register(
+    context: &Context,
+    host: &HostFunctions,
+    handle: Option<tokio::runtime::Handle>,
+) -> Result<(), ExecutionError> {
+    let (sender, receiver) = mpsc::channel();
+
+    let calls = Arc::new(HostCalls {
+        functions: host
+            .entries
+            .iter()
+            .map(|entry| (entry.key(), entry.function.clone()))
+            .collect(),
+        handle,
+        sender,
+        receiver: Mutex::new(receiver),
+    });
+
+    let host_calls = calls.clone();
+    context.add_callback("__hostCall", move |key: String, args: String| {
+        response(host_calls.call(&key, &args)).to_string()
+    })?;
+
+    let host_calls = calls.clone();
+    context.add_callback("__hostSpawn", move |id: i32, key: String, args: String| {
+        response(host_calls.spawn(id, key, &args)).to_string()
+    })?;
+
+    context.add_callback("__hostWait", move |ms: i32| {
+        let completed = calls
+            .wait(Duration::from_millis(ms.max(0) as u64))
+            .into_iter()
+            .map(|(id, result)| json!([id, response(result)]))
+            .collect();
+
+        Value::Array(completed).to_string()
+    })?;
+
+    context.eval(include_str!("./js/host.js"), false)?;
+
+    for entry in host.entries.iter() {
+        let is_async = matches!(entry.function, HostFunction::Async(_));
+        context.eval(
+            &format!(
+                "__host.define({}, {}, {}, {is_async})",
+                json!(entry.module),
+                json!(entry.name),
+                json!(entry.key()),
+            ),
+            false,
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+// CommonJS modules, see `commonjs::register`. `require` resolves and loads
+// files synchronously through `__cjsResolve` and `__cjsLoad`, imports of a
+// CommonJS file get `module.exports` from `__cjs.load`.
+(() => {
+  const { __cjsResolve: cjsResolve, __cjsLoad: cjsLoad } = globalThis;
+
+  const modules = new Map();
+  const factories = new Map();
+
+  function unwrap(response) {
+    const { ok, error } = JSON.parse(response);
+    if (error !== undefined) {
+      throw new Error(error);
+    }
+
+    return ok;
+  }
+
+  function dirname(path) {
+    const index = path.lastIndexOf("/");
+    return index < 0 ? "" : path.slice(0, index);
+  }
+
+  function load(path) {
+    const cached = modules.get(path);
+    if (cached) {
+      // partially filled while a circular require is still running
+      return cached.exports;
+    }
+
+    const module = { id: path, filename: path, exports: {}, loaded: false };
+    modules.set(path, module);
+
+    try {
+      const json = unwrap(cjsLoad(path));
+
+      if (json !== null) {
+        module.exports = JSON.parse(json);
+      } else {
+        const factory = factories.get(path);
+        factories.delete(path);
+
+        const require = requireFrom(path);
+        factory.call(
+          module.exports,
+          module.exports,
+          require,
+          module,
+          path,
+          dirname(path)
+        );
+      }
+    } catch (err) {
+      modules.delete(path);
+      throw err;
+    }
+
+    module.loaded = true;
+    return module.exports;
+  }
+
+  function requireFrom(base) {
+    const resolve = (specifier) => unwrap(cjsResolve(base, specifier));
+
+    const require = (specifier) => load(resolve(specifier));
+    require.resolve = resolve;
+    require.cache = modules;
+
+    return require;
+  }
+
+  // read-only, imports of CommonJS files in later scripts use it
+  Object.defineProperty(globalThis, "__cjs", {
+    value: Object.freeze({
+      load,
+
+      define(path, factory) {
+        factories.set(path, factory);
+      },
+    }),
+  });
+
+  // for scripts, relative to the root of the sources
+  globalThis.require = requireFrom("");
+})();
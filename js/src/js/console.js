@@ -0,0 +1,412 @@
+// The console API on top of `__console_write`, which `Context::set_console`
+// installs per script together with a bare `console` object.
+//
+// Every call is written as `__console_write(level, line, ...values)`: the
+// formatted line and the raw values for `ConsoleRecord`.
+(() => {
+  const DEPTH = 2;
+  const MAX_ITEMS = 100;
+  const LINE_WIDTH = 72;
+
+  const now = () => globalThis.performance?.now?.() ?? Date.now();
+
+  function isIdentifier(key) {
+    return /^[A-Za-z_$][\w$]*$/.test(key);
+  }
+
+  function quote(string) {
+    const escaped = string
+      .replace(/\\/g, "\\\\")
+      .replace(/'/g, "\\'")
+      .replace(/\n/g, "\\n");
+    return `'${escaped}'`;
+  }
+
+  function formatKey(key) {
+    if (typeof key === "symbol") {
+      return `[${key.toString()}]`;
+    }
+    return isIdentifier(key) ? key : quote(key);
+  }
+
+  function functionName(fn) {
+    const source = Function.prototype.toString.call(fn);
+    if (source.startsWith("class")) {
+      return fn.name ? `[class ${fn.name}]` : "[class (anonymous)]";
+    }
+    return fn.name ? `[Function: ${fn.name}]` : "[Function (anonymous)]";
+  }
+
+  function constructorName(value) {
+    const proto = Object.getPrototypeOf(value);
+    if (proto === null) {
+      return "[Object: null prototype]";
+    }
+    return proto.constructor?.name ?? "";
+  }
+
+  // Joins the parts on one line if it fits, one per line otherwise.
+  function wrap(open, parts, close, indent) {
+    if (parts.length === 0) {
+      return `${open}${close}`;
+    }
+
+    const line = `${open} ${parts.join(", ")} ${close}`;
+    if (line.length + indent.length <= LINE_WIDTH && !line.includes("\n")) {
+      return line;
+    }
+
+    const inner = indent + "  ";
+    const lines = parts.map((part) => inner + part).join(",\n");
+    return `${open}\n${lines}\n${indent}${close}`;
+  }
+
+  // util.inspect-style formatting: nested values up to `depth` levels,
+  // circular references as `[Circular]`.
+  function inspect(value, depth = DEPTH, seen = [], indent = "") {
+    switch (typeof value) {
+      case "string":
+        return seen.length ? quote(value) : value;
+      case "bigint":
+        return `${value}n`;
+      case "symbol":
+        return value.toString();
+      case "function":
+        return functionName(value);
+      case "undefined":
+        return "undefined";
+      case "object":
+        break;
+      default:
+        return Object.is(value, -0) ? "-0" : String(value);
+    }
+
+    if (value === null) {
+      return "null";
+    }
+
+    if (seen.includes(value)) {
+      return "[Circular]";
+    }
+
+    if (value instanceof Error) {
+      const stack = value.stack ? `\n${value.stack.trimEnd()}` : "";
+      const text = `${value.name}: ${value.message}${stack}`;
+      return text.replace(/\n/g, `\n${indent}`);
+    }
+    if (value instanceof Date) {
+      return isNaN(value) ? "Invalid Date" : value.toISOString();
+    }
+    if (value instanceof RegExp) {
+      return value.toString();
+    }
+    if (value instanceof Promise) {
+      return "Promise {}";
+    }
+    if (value instanceof WeakMap || value instanceof WeakSet) {
+      return `${constructorName(value)} { <items unknown> }`;
+    }
+
+    const name = constructorName(value);
+
+    if (depth < 0) {
+      if (Array.isArray(value)) {
+        return "[Array]";
+      }
+      return `[${name && !name.startsWith("[") ? name : "Object"}]`;
+    }
+
+    seen = [...seen, value];
+    const nested = (item) => inspect(item, depth - 1, seen, indent + "  ");
+    const parts = [];
+
+    if (value instanceof Map) {
+      for (const [key, item] of value) {
+        parts.push(`${nested(key)} => ${nested(item)}`);
+      }
+      return wrap(`${name}(${value.size}) {`, parts, "}", indent);
+    }
+
+    if (value instanceof Set) {
+      for (const item of value) {
+        parts.push(nested(item));
+      }
+      return wrap(`${name}(${value.size}) {`, parts, "}", indent);
+    }
+
+    if (Array.isArray(value) || ArrayBuffer.isView(value)) {
+      const length = Math.min(value.length, MAX_ITEMS);
+      let holes = 0;
+
+      for (let i = 0; i < length; i++) {
+        if (!(i in value)) {
+          holes++;
+          continue;
+        }
+        if (holes) {
+          parts.push(`<${holes} empty item${holes > 1 ? "s" : ""}>`);
+          holes = 0;
+        }
+        parts.push(nested(value[i]));
+      }
+      if (holes) {
+        parts.push(`<${holes} empty item${holes > 1 ? "s" : ""}>`);
+      }
+      if (value.length > MAX_ITEMS) {
+        const more = value.length - MAX_ITEMS;
+        parts.push(`... ${more} more item${more > 1 ? "s" : ""}`);
+      }
+
+      const prefix = name === "Array" ? "" : `${name}(${value.length}) `;
+      return wrap(`${prefix}[`, parts, "]", indent);
+    }
+
+    for (const key of Reflect.ownKeys(value)) {
+      const descriptor = Object.getOwnPropertyDescriptor(value, key);
+      if (!descriptor.enumerable) {
+        continue;
+      }
+
+      let item;
+      if (descriptor.get || descriptor.set) {
+        item = descriptor.get
+          ? descriptor.set
+            ? "[Getter/Setter]"
+            : "[Getter]"
+          : "[Setter]";
+      } else {
+        item = nested(descriptor.value);
+      }
+
+      parts.push(`${formatKey(key)}: ${item}`);
+    }
+
+    const prefix = name === "Object" ? "" : `${name} `;
+    return wrap(`${prefix}{`, parts, "}", indent);
+  }
+
+  // printf-style substitutions in a leading string: %s %d %i %f %j %o %O %c %%
+  function format(args) {
+    if (typeof args[0] !== "string" || !args[0].includes("%")) {
+      return args.map((arg) => inspect(arg)).join(" ");
+    }
+
+    let rest = args.slice(1);
+    const first = args[0].replace(/%([sdifjoOc%])/g, (match, type) => {
+      if (type === "%") {
+        return "%";
+      }
+      if (rest.length === 0) {
+        return match;
+      }
+
+      const arg = rest.shift();
+      switch (type) {
+        case "s":
+          return typeof arg === "string" ? arg : inspect(arg, 1, [], "");
+        case "d":
+        case "i": {
+          if (typeof arg === "bigint") {
+            return `${arg}n`;
+          }
+          const number = Number(arg);
+          return String(type === "i" ? Math.trunc(number) : number);
+        }
+        case "f":
+          return String(parseFloat(arg));
+        case "j":
+          try {
+            return JSON.stringify(arg);
+          } catch {
+            return "[Circular]";
+          }
+        case "o":
+          return inspect(arg, 4);
+        case "O":
+          return inspect(arg);
+        case "c":
+          return "";
+      }
+    });
+
+    return [first, ...rest.map((arg) => inspect(arg))].join(" ");
+  }
+
+  function table(data, properties) {
+    if (data === null || typeof data !== "object") {
+      return format([data]);
+    }
+
+    const entries = data instanceof Map ? [...data] : Object.entries(data);
+    const columns = [];
+    let hasValues = false;
+
+    const rows = entries.map(([index, row]) => {
+      const cells = { "(index)": String(index) };
+
+      if (row !== null && typeof row === "object") {
+        for (const key of properties ?? Object.keys(row)) {
+          if (!columns.includes(key)) {
+            columns.push(key);
+          }
+          if (key in row) {
+            cells[key] = inspect(row[key], 0, [row]);
+          }
+        }
+      } else {
+        hasValues = true;
+        cells.Values = inspect(row, 0, [data]);
+      }
+
+      return cells;
+    });
+
+    const header = ["(index)", ...columns, ...(hasValues ? ["Values"] : [])];
+    const widths = header.map((column) => {
+      const cells = rows.map((row) => (row[column] ?? "").length);
+      return Math.max(column.length, ...cells) + 2;
+    });
+
+    const line = (left, middle, right) =>
+      left + widths.map((width) => "─".repeat(width)).join(middle) + right;
+    const cells = (row) =>
+      "│" +
+      header
+        .map((column, i) => ` ${(row[column] ?? "").padEnd(widths[i] - 1)}`)
+        .join("│") +
+      "│";
+
+    return [
+      line("┌", "┬", "┐"),
+      cells(Object.fromEntries(header.map((column) => [column, column]))),
+      line("├", "┼", "┤"),
+      ...rows.map(cells),
+      line("└", "┴", "┘"),
+    ].join("\n");
+  }
+
+  // Rebuilds `console`, so counters, timers and groups start over for every
+  // script.
+  function install() {
+    const write = globalThis.__console_write;
+    const counts = new Map();
+    const timers = new Map();
+    let indent = "";
+
+    const emit = (level, line, values) => {
+      const text = indent ? indent + line.replace(/\n/g, `\n${indent}`) : line;
+      write(level, text, ...values);
+    };
+
+    const logger = (level) => (...args) => emit(level, format(args), args);
+
+    const elapsed = (label) => {
+      const ms = now() - timers.get(label);
+      return `${label}: ${ms.toFixed(3)}ms`;
+    };
+
+    const group = (...label) => {
+      if (label.length) {
+        emit("log", format(label), label);
+      }
+      indent += "  ";
+    };
+
+    globalThis.console = {
+      log: logger("log"),
+      info: logger("info"),
+      warn: logger("warn"),
+      error: logger("error"),
+      debug: logger("debug"),
+      dirxml: logger("log"),
+
+      dir(value, options) {
+        emit("log", inspect(value, options?.depth ?? DEPTH), [value]);
+      },
+
+      trace(...args) {
+        // the first frame is `trace` itself
+        const stack = (new Error().stack ?? "")
+          .split("\n")
+          .slice(1)
+          .join("\n")
+          .trimEnd();
+        const message = args.length ? `Trace: ${format(args)}` : "Trace";
+        emit("trace", stack ? `${message}\n${stack}` : message, args);
+      },
+
+      assert(condition, ...args) {
+        if (!condition) {
+          const message = args.length
+            ? `Assertion failed: ${format(args)}`
+            : "Assertion failed";
+          emit("error", message, args);
+        }
+      },
+
+      count(label = "default") {
+        const count = (counts.get(label) ?? 0) + 1;
+        counts.set(label, count);
+        const line = `${label}: ${count}`;
+        emit("info", line, [line]);
+      },
+
+      countReset(label = "default") {
+        if (counts.has(label)) {
+          counts.set(label, 0);
+        } else {
+          const line = `Count for '${label}' does not exist`;
+          emit("warn", line, [line]);
+        }
+      },
+
+      time(label = "default") {
+        if (timers.has(label)) {
+          const line = `Timer '${label}' already exists`;
+          emit("warn", line, [line]);
+          return;
+        }
+        timers.set(label, now());
+      },
+
+      timeLog(label = "default", ...args) {
+        if (!timers.has(label)) {
+          const line = `Timer '${label}' does not exist`;
+          emit("warn", line, [line]);
+          return;
+        }
+        const line = [elapsed(label), ...args.map((arg) => inspect(arg))].join(
+          ", "
+        );
+        emit("log", line, [line, ...args]);
+      },
+
+      timeEnd(label = "default") {
+        if (!timers.has(label)) {
+          const line = `Timer '${label}' does not exist`;
+          emit("warn", line, [line]);
+          return;
+        }
+        const line = elapsed(label);
+        timers.delete(label);
+        emit("info", line, [line]);
+      },
+
+      table(data, properties) {
+        emit("log", table(data, properties), [data]);
+      },
+
+      group,
+      groupCollapsed: group,
+
+      groupEnd() {
+        indent = indent.slice(2);
+      },
+    };
+  }
+
+  // read-only, the worker installs the console before every script
+  Object.defineProperty(globalThis, "__console", {
+    value: Object.freeze({ inspect, install }),
+  });
+})();
@@ -0,0 +1,76 @@
+// Glue for the Rust host functions, see `host::register`.
+(() => {
+  // the callbacks registered by `host::register`
+  const { __hostCall: hostCall, __hostSpawn: hostSpawn, __hostWait: hostWait } =
+    globalThis;
+
+  const pending = new Map();
+  let nextId = 1;
+
+  function unwrap({ ok, error }) {
+    if (error !== undefined) {
+      throw new Error(error);
+    }
+
+    return ok;
+  }
+
+  function call(key, args) {
+    return unwrap(JSON.parse(hostCall(key, JSON.stringify(args))));
+  }
+
+  function spawn(key, args) {
+    return new Promise((resolve, reject) => {
+      const id = nextId++;
+      unwrap(JSON.parse(hostSpawn(id, key, JSON.stringify(args))));
+      pending.set(id, { resolve, reject });
+    });
+  }
+
+  const host = {
+    modules: {},
+
+    define(module, name, key, isAsync) {
+      const fn = isAsync
+        ? (...args) => spawn(key, args)
+        : (...args) => call(key, args);
+
+      if (module === null) {
+        globalThis[name] = fn;
+      } else {
+        (this.modules[module] ??= {})[name] = fn;
+      }
+    },
+
+    pending() {
+      return pending.size;
+    },
+
+    // Waits up to `ms` for async host functions and settles their promises.
+    wait(ms) {
+      for (const [id, response] of JSON.parse(hostWait(ms))) {
+        const call = pending.get(id);
+
+        // left over from an interrupted script
+        if (!call) {
+          continue;
+        }
+
+        pending.delete(id);
+
+        try {
+          call.resolve(unwrap(response));
+        } catch (error) {
+          call.reject(error);
+        }
+      }
+    },
+
+    clear() {
+      pending.clear();
+    },
+  };
+
+  // read-only, the worker calls it between scripts
+  Object.defineProperty(globalThis, "__host", { value: Object.freeze(host) });
+})();
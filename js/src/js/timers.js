@@ -0,0 +1,78 @@
+// Timers are driven by the worker's event loop, see `context::run_event_loop`.
+(() => {
+  const timers = new Map();
+  let nextId = 1;
+
+  function schedule(callback, delay, args, repeat) {
+    if (typeof callback !== "function") {
+      throw new TypeError("callback must be a function");
+    }
+
+    delay = Math.max(0, Number(delay) || 0);
+
+    const id = nextId++;
+    timers.set(id, { callback, args, delay, repeat, at: Date.now() + delay });
+    return id;
+  }
+
+  globalThis.setTimeout = (callback, delay, ...args) =>
+    schedule(callback, delay, args, false);
+
+  globalThis.setInterval = (callback, delay, ...args) =>
+    schedule(callback, delay, args, true);
+
+  globalThis.clearTimeout = (id) => {
+    timers.delete(id);
+  };
+
+  globalThis.clearInterval = globalThis.clearTimeout;
+
+  globalThis.queueMicrotask = (callback) => {
+    if (typeof callback !== "function") {
+      throw new TypeError("callback must be a function");
+    }
+
+    Promise.resolve().then(() => callback());
+  };
+
+  // Runs the earliest due timer.
+  // Returns 0 if a timer ran, the ms until the next one, or -1 if there are
+  // none left.
+  function runTimers() {
+    let next;
+
+    for (const [id, timer] of timers) {
+      if (!next || timer.at < next[1].at) {
+        next = [id, timer];
+      }
+    }
+
+    if (!next) {
+      return -1;
+    }
+
+    const [id, timer] = next;
+    const now = Date.now();
+
+    if (timer.at > now) {
+      return Math.ceil(timer.at - now);
+    }
+
+    if (timer.repeat) {
+      timer.at = now + Math.max(1, timer.delay);
+    } else {
+      timers.delete(id);
+    }
+
+    timer.callback(...timer.args);
+    return 0;
+  }
+
+  function clearTimers() {
+    timers.clear();
+  }
+
+  // read-only, so a script can't break the event loop of later scripts
+  Object.defineProperty(globalThis, "__runTimers", { value: runTimers });
+  Object.defineProperty(globalThis, "__clearTimers", { value: clearTimers });
+})();
@@ -0,0 +1,234 @@
+use quickjs_rusty::OwnedJsValue;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::Error;
+
+/// A JS exception converted into plain Rust data, so it can leave the worker,
+/// be logged or rendered on an error page.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JsError {
+    /// e.g. `TypeError`, empty if something other than an `Error` was thrown
+    pub name: String,
+    pub message: String,
+    /// Innermost frame first
+    pub stack: Vec<StackFrame>,
+    /// Where the error was thrown, the first frame with a known location
+    pub location: Option<SourceLocation>,
+}
+
+/// A function or page that failed to load, see `Runtime::try_new`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// Function name, page path or page pattern
+    pub source: String,
+    pub message: String,
+    /// May point into a module imported by `source`
+    pub location: Option<SourceLocation>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StackFrame {
+    /// `<eval>` for top level code, `<anonymous>` for anonymous functions
+    pub function: Option<String>,
+    /// `None` for native functions
+    pub location: Option<SourceLocation>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl JsError {
+    /// Reads name, message and stack of a thrown value.
+    pub(crate) fn from_exception(value: &OwnedJsValue) -> Self {
+        let object = value
+            .is_object()
+            .then(|| value.clone().try_into_object().ok());
+
+        let Some(Some(object)) = object else {
+            let message = value.js_to_string().unwrap_or_default();
+            return Self::from_message(&message);
+        };
+
+        let property = |name: &str| {
+            object
+                .property(name)
+                .ok()
+                .flatten()
+                .filter(|value| !value.is_undefined() && !value.is_null())
+                .and_then(|value| value.js_to_string().ok())
+        };
+
+        let Some(message) = property("message") else {
+            return Self::from_message(&value.js_to_string().unwrap_or_default());
+        };
+
+        let stack = property("stack")
+            .map(|stack| parse_stack(&stack))
+            .unwrap_or_default();
+
+        Self {
+            name: property("name").unwrap_or_default(),
+            message,
+            location: stack.iter().find_map(|frame| frame.location.clone()),
+            stack,
+        }
+    }
+
+    /// Errors that were already turned into a string, e.g. `"SyntaxError: unexpected token"`.
+    pub(crate) fn from_message(message: &str) -> Self {
+        let (name, message) = match message.split_once(": ") {
+            Some((name, message)) if is_error_name(name) => (name, message),
+            _ => ("", message),
+        };
+
+        Self {
+            name: name.into(),
+            message: message.into(),
+            stack: Vec::new(),
+            location: None,
+        }
+    }
+}
+
+impl Diagnostic {
+    pub(crate) fn new(source: &str, error: &Error) -> Self {
+        match error {
+            Error::Js(err) if err.location.is_some() => Self {
+                source: source.into(),
+                message: err.to_string(),
+                location: err.location.clone(),
+            },
+            #[cfg(feature = "transpiling")]
+            Error::Parse(diagnostic) => {
+                let position = diagnostic.display_position();
+                Self {
+                    source: source.into(),
+                    message: deno_ast::diagnostics::Diagnostic::message(diagnostic).to_string(),
+                    location: Some(SourceLocation {
+                        file: source.into(),
+                        line: position.line_number as u32,
+                        column: position.column_number as u32,
+                    }),
+                }
+            }
+            err => Self::from_message(source, &err.to_string()),
+        }
+    }
+
+    /// Transpiler errors from the module loader end in ` at file://path:line:col`,
+    /// followed by a code excerpt.
+    pub(crate) fn from_message(source: &str, message: &str) -> Self {
+        let line = message.lines().next().unwrap_or_default();
+
+        let location = line.rsplit_once(" at ").and_then(|(message, location)| {
+            let location = parse_location(location.trim_start_matches("file://"))?;
+            Some((message, location))
+        });
+
+        match location {
+            Some((message, location)) => Self {
+                source: source.into(),
+                message: message.into(),
+                location: Some(location),
+            },
+            None => Self {
+                source: source.into(),
+                message: line.into(),
+                location: None,
+            },
+        }
+    }
+}
+
+fn is_error_name(name: &str) -> bool {
+    name.ends_with("Error") && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Parses QuickJS stack lines: `at inner (script.js:2:30)`, `at map (native)`
+/// or `at <input>:1:1`.
+fn parse_stack(stack: &str) -> Vec<StackFrame> {
+    stack
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("at "))
+        .map(
+            |frame| match frame.strip_suffix(')').and_then(|f| f.split_once(" (")) {
+                Some((function, location)) => StackFrame {
+                    function: Some(function.into()),
+                    location: parse_location(location),
+                },
+                None => StackFrame {
+                    function: None,
+                    location: parse_location(frame),
+                },
+            },
+        )
+        .collect()
+}
+
+fn parse_location(location: &str) -> Option<SourceLocation> {
+    let mut parts = location.rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line = parts.next()?.parse().ok()?;
+    let file = parts.next()?;
+
+    Some(SourceLocation {
+        file: file.into(),
+        line,
+        column,
+    })
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location = match &self.location {
+            Some(location) => location.to_string(),
+            None => "native".into(),
+        };
+
+        match &self.function {
+            Some(function) => write!(f, "{function} ({location})"),
+            None => write!(f, "{location}"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{}: {location}: {}", self.source, self.message),
+            None => write!(f, "{}: {}", self.source, self.message),
+        }
+    }
+}
+
+/// `{}` shows `name: message`, `{:#}` adds the stack.
+impl fmt::Display for JsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "{}", self.message)?;
+        } else {
+            write!(f, "{}: {}", self.name, self.message)?;
+        }
+
+        if f.alternate() {
+            for frame in &self.stack {
+                write!(f, "\n    at {frame}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for JsError {}
@@ -1,7 +1,20 @@
+mod assets;
+mod commonjs;
+mod console;
 mod context;
+mod host;
+mod js_error;
+mod module_source;
+mod resolve;
 mod runtime;
+mod source_map;
 
+pub use console::{ConsoleLevel, ConsoleRecord};
+pub use host::HostFunctions;
+pub use js_error::{Diagnostic, JsError, SourceLocation, StackFrame};
+pub use module_source::{DiskSource, ModuleSource};
 use quickjs_rusty::{ExecutionError, ValueError};
+pub use resolve::ImportMap;
 pub use runtime::*;
 
 #[derive(thiserror::Error, Debug)]
@@ -13,6 +26,10 @@ pub enum Error {
     #[error(transparent)]
     Serde(#[from] quickjs_rusty::serde::Error),
     #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Js(JsError),
+    #[error(transparent)]
     Context(#[from] quickjs_rusty::ContextError),
 
     #[cfg(feature = "transpiling")]
@@ -22,6 +39,26 @@ pub enum Error {
     #[error(transparent)]
     Transpile(#[from] deno_ast::TranspileError),
 
+    #[error("script execution timed out after {0:?}")]
+    Timeout(std::time::Duration),
+    #[error("script exceeded the memory limit")]
+    OutOfMemory,
+    #[error("script exceeded the maximum stack size")]
+    StackOverflow,
+    #[error("worker crashed while executing the script")]
+    WorkerCrashed,
+    #[error("runtime queue is full")]
+    Overloaded,
+    #[error("script execution was cancelled")]
+    Cancelled,
+    #[error("runtime is shutting down")]
+    ShuttingDown,
+
+    #[error("failed to load functions or pages:{}", .0.iter().map(|d| format!("\n  {d}")).collect::<String>())]
+    Compile(Vec<Diagnostic>),
+    #[error("invalid configuration: {0}")]
+    Config(String),
+
     #[error("unexpected")]
     Unexpected(String),
 }
@@ -0,0 +1,197 @@
+use include_dir::Dir;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+
+/// Where modules and pages are loaded from. Paths are relative to the root
+/// and use `/` as separator.
+///
+/// `include_dir::Dir` embeds the sources in the binary, `DiskSource` reads
+/// them from a directory and can reload them while the runtime is running.
+pub trait ModuleSource: Send + Sync {
+    fn read(&self, path: &str) -> Option<Cow<'_, [u8]>>;
+
+    fn is_dir(&self, path: &str) -> bool;
+
+    fn is_file(&self, path: &str) -> bool {
+        self.read(path).is_some()
+    }
+
+    /// All files in `dir` and its subdirectories, `""` for the root.
+    fn files(&self, dir: &str) -> Vec<String>;
+
+    /// Changes whenever a file does, workers rebuild their context when it
+    /// differs from the version they were built from.
+    fn version(&self) -> u64 {
+        0
+    }
+}
+
+impl ModuleSource for Dir<'static> {
+    fn read(&self, path: &str) -> Option<Cow<'_, [u8]>> {
+        self.get_file(path)
+            .map(|file| Cow::Borrowed(file.contents()))
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        path.is_empty() || self.get_dir(path).is_some()
+    }
+
+    fn is_file(&self, path: &str) -> bool {
+        self.get_file(path).is_some()
+    }
+
+    fn files(&self, dir: &str) -> Vec<String> {
+        fn collect(dir: &Dir, files: &mut Vec<String>) {
+            files.extend(
+                dir.files()
+                    .map(|file| file.path().to_string_lossy().into_owned()),
+            );
+            dir.dirs().for_each(|dir| collect(dir, files));
+        }
+
+        let mut files = Vec::new();
+
+        match dir {
+            "" => collect(self, &mut files),
+            dir => {
+                if let Some(dir) = self.get_dir(dir) {
+                    collect(dir, &mut files);
+                }
+            }
+        }
+
+        files
+    }
+}
+
+/// Sources in a directory on disk, meant for development.
+///
+/// With `watch`, a change to any file makes every worker rebuild its context
+/// before running the next script, so edited pages and functions are picked
+/// up without restarting the server.
+pub struct DiskSource {
+    root: PathBuf,
+    version: Arc<AtomicU64>,
+}
+
+impl DiskSource {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            version: Arc::default(),
+        }
+    }
+
+    /// Checks the files for changes every `interval` until the source is dropped.
+    pub fn watch(self, interval: Duration) -> Self {
+        let root = self.root.clone();
+        let version = Arc::downgrade(&self.version);
+
+        std::thread::spawn(move || watch(root, version, interval));
+
+        self
+    }
+
+    /// `None` for paths leaving the root.
+    fn path(&self, path: &str) -> Option<PathBuf> {
+        let path = Path::new(path.trim_start_matches('/'));
+
+        path.components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+            .then(|| self.root.join(path))
+    }
+}
+
+impl ModuleSource for DiskSource {
+    fn read(&self, path: &str) -> Option<Cow<'_, [u8]>> {
+        std::fs::read(self.path(path)?).ok().map(Cow::Owned)
+    }
+
+    fn is_dir(&self, path: &str) -> bool {
+        self.path(path).is_some_and(|path| path.is_dir())
+    }
+
+    fn is_file(&self, path: &str) -> bool {
+        self.path(path).is_some_and(|path| path.is_file())
+    }
+
+    fn files(&self, dir: &str) -> Vec<String> {
+        let Some(path) = self.path(dir) else {
+            return Vec::new();
+        };
+
+        let mut files = Vec::new();
+        walk(&path, &mut files);
+
+        files
+            .iter()
+            .filter_map(|file| file.strip_prefix(&self.root).ok())
+            .map(|file| {
+                file.components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .collect()
+    }
+
+    fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            walk(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+type Snapshot = HashMap<PathBuf, (Option<SystemTime>, u64)>;
+
+fn snapshot(root: &Path) -> Snapshot {
+    let mut files = Vec::new();
+    walk(root, &mut files);
+
+    files
+        .into_iter()
+        .map(|file| {
+            let metadata = std::fs::metadata(&file).ok();
+            let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+            let len = metadata.map(|m| m.len()).unwrap_or_default();
+            (file, (modified, len))
+        })
+        .collect()
+}
+
+fn watch(root: PathBuf, version: Weak<AtomicU64>, interval: Duration) {
+    let mut files = snapshot(&root);
+
+    loop {
+        std::thread::sleep(interval);
+
+        let Some(version) = version.upgrade() else {
+            break;
+        };
+
+        let current = snapshot(&root);
+
+        if current != files {
+            log::info!("{} changed, reloading", root.display());
+            version.fetch_add(1, Ordering::Release);
+            files = current;
+        }
+    }
+}
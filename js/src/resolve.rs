@@ -0,0 +1,404 @@
+use crate::{Error, module_source::ModuleSource};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Tried in this order for imports without an extension and for directories.
+const EXTENSIONS: [&str; 4] = ["ts", "tsx", "js", "jsx"];
+
+/// How a module is loaded, decides which entry of a package is used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Import,
+    Require,
+}
+
+impl Kind {
+    /// Conditions of a package's `exports` by priority. Imports fall back to
+    /// CommonJS, which the loader wraps.
+    fn conditions(self) -> &'static [&'static str] {
+        match self {
+            Kind::Import => &["import", "module", "default", "require", "node"],
+            Kind::Require => &["require", "node", "default", "import", "module"],
+        }
+    }
+}
+
+/// A [WICG import map](https://github.com/WICG/import-maps), e.g.
+/// `{"imports": {"@/": "./src/"}}`. Targets are paths in the module source.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportMap {
+    #[serde(default)]
+    pub imports: BTreeMap<String, String>,
+    /// Imports for the modules below a path, e.g. `"./pages/"`
+    #[serde(default)]
+    pub scopes: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl ImportMap {
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Converts `compilerOptions.paths`, relative to `baseUrl` and the source
+    /// root. Only the first target of a path is used, `extends` is ignored.
+    pub fn from_tsconfig(json: &str) -> Result<Self, Error> {
+        let tsconfig = serde_json::from_str::<Value>(&strip_jsonc(json))?;
+        let options = &tsconfig["compilerOptions"];
+        let base_url = options["baseUrl"].as_str().unwrap_or(".");
+
+        let mut imports = BTreeMap::new();
+
+        for (key, targets) in options["paths"].as_object().into_iter().flatten() {
+            let Some(target) = targets.get(0).and_then(Value::as_str) else {
+                continue;
+            };
+            let target = join_clean(base_url, target);
+
+            match (key.strip_suffix('*'), target.strip_suffix('*')) {
+                (Some(prefix), Some(target)) if prefix.ends_with('/') => {
+                    imports.insert(prefix.to_string(), target.to_string());
+                }
+                _ if !key.contains('*') && !target.contains('*') => {
+                    imports.insert(key.clone(), target);
+                }
+                _ => log::warn!("tsconfig path '{key}' is not supported in import maps"),
+            }
+        }
+
+        Ok(Self {
+            imports,
+            scopes: BTreeMap::new(),
+        })
+    }
+
+    /// The path `specifier` maps to when imported from `base`: the most
+    /// specific scope first, the longest prefix within it.
+    fn map(&self, base: &str, specifier: &str) -> Option<String> {
+        let base = base.trim_start_matches('/');
+
+        let mut scopes = self
+            .scopes
+            .iter()
+            .map(|(scope, imports)| (clean(scope), imports))
+            .filter(|(scope, _)| base.starts_with(scope))
+            .collect::<Vec<_>>();
+        scopes.sort_by_key(|(scope, _)| std::cmp::Reverse(scope.len()));
+
+        scopes
+            .into_iter()
+            .map(|(_, imports)| imports)
+            .chain([&self.imports])
+            .find_map(|imports| lookup(imports, specifier))
+    }
+}
+
+fn lookup(imports: &BTreeMap<String, String>, specifier: &str) -> Option<String> {
+    if let Some(target) = imports.get(specifier) {
+        return Some(clean(target).to_string());
+    }
+
+    imports
+        .iter()
+        .filter(|(key, _)| key.ends_with('/') && specifier.starts_with(key.as_str()))
+        .max_by_key(|(key, _)| key.len())
+        .map(|(key, target)| format!("{}{}", clean(target), &specifier[key.len()..]))
+}
+
+/// Resolves an import to a file in the module source. `path` is the
+/// specifier joined with the importer's directory if it is relative.
+///
+/// Errors are short enough for a QuickJS error message.
+pub(crate) fn resolve(
+    source: &dyn ModuleSource,
+    import_map: Option<&ImportMap>,
+    kind: Kind,
+    base: &str,
+    specifier: &str,
+    path: &str,
+) -> Result<String, String> {
+    if let Some(mapped) = import_map.and_then(|import_map| import_map.map(base, specifier)) {
+        return file(source, &mapped).map_err(|tried| format!("not found, tried: {tried}"));
+    }
+
+    if is_bare(specifier)
+        && let Some(resolved) = package(source, kind, base, specifier)
+    {
+        return resolved;
+    }
+
+    file(source, path).map_err(|tried| format!("not found, tried: {tried}"))
+}
+
+fn is_bare(specifier: &str) -> bool {
+    !(specifier.starts_with("./") || specifier.starts_with("../") || specifier.starts_with('/'))
+}
+
+/// Resolves like TS and Node do: the path itself, with one of the
+/// `EXTENSIONS`, or the `index` file of the directory. Returns the candidates
+/// tried if none exists.
+fn file(source: &dyn ModuleSource, path: &str) -> Result<String, String> {
+    let path = path.trim_end_matches('/');
+
+    let index = match path {
+        "" => "index".to_string(),
+        path => format!("{path}/index"),
+    };
+
+    let candidates = std::iter::once(path.to_string())
+        .filter(|path| !path.is_empty())
+        .chain(EXTENSIONS.iter().map(|ext| format!("{path}.{ext}")))
+        .chain(EXTENSIONS.iter().map(|ext| format!("{index}.{ext}")));
+
+    for candidate in candidates {
+        if source.is_file(&candidate) {
+            return Ok(candidate);
+        }
+    }
+
+    let extensions = EXTENSIONS.join(",");
+    Err(format!(
+        "{path}, {path}.{{{extensions}}}, {index}.{{{extensions}}}"
+    ))
+}
+
+/// Resolves `date-fns` or `date-fns/locale` from the closest `node_modules`
+/// directory, `None` if none has the package.
+fn package(
+    source: &dyn ModuleSource,
+    kind: Kind,
+    base: &str,
+    specifier: &str,
+) -> Option<Result<String, String>> {
+    let (name, subpath) = split_package(specifier);
+    let dir = package_dir(source, base, name)?;
+
+    Some(package_entry(source, kind, &dir, name, &subpath))
+}
+
+/// `@scope/name/sub` is the package `@scope/name` and the subpath `./sub`.
+fn split_package(specifier: &str) -> (&str, String) {
+    let end = match specifier.starts_with('@') {
+        true => specifier.match_indices('/').nth(1).map(|(i, _)| i),
+        false => specifier.find('/'),
+    }
+    .unwrap_or(specifier.len());
+
+    (&specifier[..end], format!(".{}", &specifier[end..]))
+}
+
+/// Looks in `node_modules` next to the importer and in each parent directory.
+fn package_dir(source: &dyn ModuleSource, base: &str, name: &str) -> Option<String> {
+    let mut dir = parent(base);
+
+    loop {
+        if dir.rsplit('/').next() != Some("node_modules") {
+            let candidate = join(dir, &format!("node_modules/{name}"));
+            if source.is_dir(&candidate) {
+                return Some(candidate);
+            }
+        }
+
+        if dir.is_empty() {
+            return None;
+        }
+        dir = parent(dir);
+    }
+}
+
+fn package_entry(
+    source: &dyn ModuleSource,
+    kind: Kind,
+    dir: &str,
+    name: &str,
+    subpath: &str,
+) -> Result<String, String> {
+    let package = match source.read(&format!("{dir}/package.json")) {
+        Some(json) => serde_json::from_slice::<Value>(&json)
+            .map_err(|err| format!("invalid package.json of '{name}': {err}"))?,
+        None => Value::Null,
+    };
+
+    let target = match package.get("exports") {
+        Some(exports) => exports_target(exports, kind, name, subpath)?,
+        None if subpath == "." => {
+            let (module, main) = (package["module"].as_str(), package["main"].as_str());
+            let entry = match kind {
+                Kind::Import => module.or(main),
+                Kind::Require => main.or(module),
+            };
+            entry.unwrap_or("index").to_string()
+        }
+        None => subpath.to_string(),
+    };
+
+    file(source, &join(dir, target.trim_start_matches("./")))
+        .map_err(|tried| format!("entry of '{name}' not found, tried: {tried}"))
+}
+
+/// The target of `subpath` in `exports`.
+fn exports_target(
+    exports: &Value,
+    kind: Kind,
+    name: &str,
+    subpath: &str,
+) -> Result<String, String> {
+    let not_exported = || format!("'{subpath}' is not exported by '{name}'");
+
+    let subpaths = exports
+        .as_object()
+        .filter(|map| map.keys().any(|key| key.starts_with('.')));
+
+    // `"exports": "./index.js"` or conditions only export the package itself
+    let (value, replacement) = match subpaths {
+        None if subpath == "." => (exports, None),
+        None => return Err(not_exported()),
+        Some(map) => match map.get(subpath) {
+            Some(value) => (value, None),
+            // `"./utils/*": "./dist/utils/*.js"`, the longest prefix wins
+            None => map
+                .iter()
+                .filter_map(|(key, value)| {
+                    let (prefix, suffix) = key.split_once('*')?;
+                    let middle = subpath.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                    Some((prefix.len(), value, middle))
+                })
+                .max_by_key(|(len, ..)| *len)
+                .map(|(_, value, middle)| (value, Some(middle)))
+                .ok_or_else(not_exported)?,
+        },
+    };
+
+    let target = conditional(value, kind.conditions()).ok_or_else(not_exported)?;
+
+    Ok(match replacement {
+        Some(middle) => target.replace('*', middle),
+        None => target,
+    })
+}
+
+/// Picks the target for the first matching condition, nested conditions and
+/// fallback arrays included.
+fn conditional(value: &Value, conditions: &[&str]) -> Option<String> {
+    match value {
+        Value::String(target) => Some(target.clone()),
+        Value::Array(targets) => targets
+            .iter()
+            .find_map(|target| conditional(target, conditions)),
+        Value::Object(map) => conditions
+            .iter()
+            .find_map(|condition| conditional(map.get(*condition)?, conditions)),
+        _ => None,
+    }
+}
+
+/// `./src/` is `src/`.
+fn clean(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+/// `join_clean(".", "./src/*")` is `src/*`.
+fn join_clean(dir: &str, path: &str) -> String {
+    format!("{dir}/{path}")
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// tsconfig.json allows comments and trailing commas.
+fn strip_jsonc(json: &str) -> String {
+    let mut out = String::with_capacity(json.len());
+    let mut chars = json.chars().peekable();
+    let mut in_string = false;
+    // written once the next token shows it isn't trailing
+    let mut comma = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('/', Some('/')) => while chars.next_if(|&c| c != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    if c == '*' && chars.next_if_eq(&'/').is_some() {
+                        break;
+                    }
+                }
+            }
+            _ if c.is_whitespace() => out.push(c),
+            (',', _) => {
+                if comma {
+                    out.push(',');
+                }
+                comma = true;
+            }
+            _ => {
+                if std::mem::take(&mut comma) && !matches!(c, '}' | ']') {
+                    out.push(',');
+                }
+                in_string = c == '"';
+                out.push(c);
+            }
+        }
+    }
+
+    if comma {
+        out.push(',');
+    }
+
+    out
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+fn join(dir: &str, path: &str) -> String {
+    match dir {
+        "" => path.to_string(),
+        dir => format!("{dir}/{path}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn jsonc() {
+        let strip = |json: &str| {
+            let stripped = strip_jsonc(json);
+            serde_json::from_str::<serde_json::Value>(&stripped)
+                .unwrap_or_else(|err| panic!("{stripped}: {err}"))
+        };
+
+        assert_eq!(
+            strip(r#"{"a": [1, 2,], "b": 3,}"#),
+            json!({"a": [1, 2], "b": 3})
+        );
+        assert_eq!(
+            strip("{\n  // comment\n  \"a\": 1, /* more */ \"b\": 2\n}"),
+            json!({"a": 1, "b": 2})
+        );
+        // comments after a trailing comma
+        assert_eq!(strip("{\"a\": 1, // aliases\n}"), json!({"a": 1}));
+        assert_eq!(strip("{\"a\": [1],\n /* x */ }"), json!({"a": [1]}));
+        // strings are left alone
+        assert_eq!(
+            strip(r#"{"a": "//, */ ,}", "b": "\" ,]"}"#),
+            json!({"a": "//, */ ,}", "b": "\" ,]"})
+        );
+    }
+}
@@ -1,13 +1,18 @@
 use crate::{
-    Error,
-    context::{self, Function},
+    ConsoleRecord, Diagnostic, Error, HostFunctions, ImportMap,
+    console::{Console, ScriptInfo},
+    context::{self, CompiledFunction, Function},
+    module_source::ModuleSource,
 };
-use include_dir::{Dir, DirEntry};
-use quickjs_rusty::JsCompiledFunction;
+use include_dir::Dir;
 
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 #[cfg(feature = "with-axum")]
 use axum::extract::FromRef;
@@ -20,6 +25,18 @@ impl axum::response::IntoResponse for Error {
                 log::error!("{:?}", msg.to_string());
                 "Execution error".into_response()
             }
+            Error::Js(err) => {
+                log::error!("{err:#}");
+                "Execution error".into_response()
+            }
+            Error::Overloaded | Error::ShuttingDown => {
+                log::warn!("rejecting request: {}", self);
+                (
+                    axum::http::StatusCode::SERVICE_UNAVAILABLE,
+                    "Service unavailable",
+                )
+                    .into_response()
+            }
             err => {
                 log::error!("{:?}", err);
                 "Unhandled error".into_response()
@@ -34,38 +51,136 @@ pub enum Script {
     Function {
         args: Option<Value>,
         code: String,
+        /// Overrides `RuntimeConfig::timeout`
+        #[serde(default)]
+        timeout: Option<Duration>,
     },
     #[cfg(all(feature = "transpiling", feature = "pages"))]
     RenderPage {
         args: Option<Value>,
         name: String,
+        /// Overrides `RuntimeConfig::timeout`
+        #[serde(default)]
+        timeout: Option<Duration>,
     },
     CompiledFunction {
         args: Option<Value>,
         name: String,
+        /// Overrides `RuntimeConfig::timeout`
+        #[serde(default)]
+        timeout: Option<Duration>,
     },
 }
 
+impl Script {
+    fn timeout(&self) -> Option<Duration> {
+        match self {
+            Script::Function { timeout, .. } => *timeout,
+            #[cfg(all(feature = "transpiling", feature = "pages"))]
+            Script::RenderPage { timeout, .. } => *timeout,
+            Script::CompiledFunction { timeout, .. } => *timeout,
+        }
+    }
+
+    fn info(&self, worker: usize) -> ScriptInfo {
+        let mut info = ScriptInfo {
+            worker,
+            ..Default::default()
+        };
+
+        match self {
+            Script::Function { .. } => {}
+            #[cfg(all(feature = "transpiling", feature = "pages"))]
+            Script::RenderPage { name, .. } => info.page = name.clone(),
+            Script::CompiledFunction { name, .. } => info.script = name.clone(),
+        }
+
+        info
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct ScriptOutput {
     pub output: String,
     pub console_output: String,
+    /// Every `console.*` call, in order
+    pub console: Vec<ConsoleRecord>,
 }
 
 enum Message {
     ExecuteScript {
         script: Script,
         respond_to: tokio::sync::oneshot::Sender<Result<ScriptOutput, Error>>,
+        /// Queue slot, released as soon as a worker picks up the message
+        permit: Option<tokio::sync::OwnedSemaphorePermit>,
+        /// Set when the caller stops waiting for the result
+        cancelled: Arc<AtomicBool>,
+        /// Output the result as JSON, for `execute_script_as`
+        json: bool,
     },
+    /// Sent once per worker on shutdown
+    Stop,
+    /// Makes the worker panic, to test respawning
+    #[cfg(test)]
+    Panic,
+}
+
+/// Marks the message as cancelled if the `execute_script` future is dropped early.
+struct CancelOnDrop(Option<Arc<AtomicBool>>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(cancelled) = self.0.take() {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// What `execute_script` does when the queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Wait until a queue slot becomes available
+    #[default]
+    Wait,
+    /// Fail immediately with `Error::Overloaded`
+    Reject,
 }
 
 pub struct RuntimeConfig<'a> {
     pub workers: usize,
     pub functions: Option<HashMap<String, String>>,
     pub js_src_dir: Option<Dir<'a>>,
-    /// default: "pages"
+    /// Used instead of `js_src_dir`, e.g. a watched `DiskSource` to reload
+    /// pages and modules while developing
+    pub module_source: Option<Arc<dyn ModuleSource>>,
+    /// Aliases like `@/components/item`, default: the `paths` of a
+    /// `tsconfig.json` in the sources, read once at startup
+    pub import_map: Option<ImportMap>,
+    /// Maximum execution time of a single script, default: no limit
+    pub timeout: Option<Duration>,
+    /// Heap limit of each worker in bytes, default: no limit
+    pub memory_limit: Option<usize>,
+    /// Stack limit of each worker in bytes, default: QuickJS default (1 MiB)
+    pub max_stack_size: Option<usize>,
+    /// Allocated bytes that trigger garbage collection, default: QuickJS default
+    pub gc_threshold: Option<usize>,
+    /// Maximum number of scripts waiting for a worker, default: unbounded
+    pub queue_capacity: Option<usize>,
+    /// default: `QueuePolicy::Wait`
+    pub queue_policy: QueuePolicy,
+    /// Rust functions callable from scripts and pages.
+    /// Async functions run on the tokio runtime `Runtime::new` is called from.
+    pub host_functions: HostFunctions,
+    /// Send console calls to `tracing` events (target `js::console`) with the
+    /// script, page and worker as fields, instead of `log::debug!`
+    #[cfg(feature = "tracing")]
+    pub console_tracing: bool,
+    /// Directories in `js_src_dir` searched for server pages, default: `["pages"]`
+    #[cfg(feature = "pages")]
+    pub pages_dirs: Vec<String>,
+    /// Glob matched against page file names, default: "*.page.[tj]sx"
     #[cfg(feature = "pages")]
-    pub pages_dir: String,
+    pub page_pattern: String,
 }
 
 impl<'a> Default for RuntimeConfig<'a> {
@@ -74,147 +189,558 @@ impl<'a> Default for RuntimeConfig<'a> {
             workers: 5,
             functions: Some(HashMap::new()),
             js_src_dir: None,
+            module_source: None,
+            import_map: None,
+            timeout: None,
+            memory_limit: None,
+            max_stack_size: None,
+            gc_threshold: None,
+            queue_capacity: None,
+            queue_policy: QueuePolicy::Wait,
+            host_functions: HostFunctions::default(),
+            #[cfg(feature = "tracing")]
+            console_tracing: false,
+            #[cfg(feature = "pages")]
+            pages_dirs: vec!["pages".into()],
+            #[cfg(feature = "pages")]
+            page_pattern: "*.page.[tj]sx".into(),
+        }
+    }
+}
+
+/// Field order matters: compiled functions must be dropped before their context.
+struct WorkerContext {
+    compiled_fns: HashMap<String, CompiledFunction>,
+    context: quickjs_rusty::Context,
+    /// `ModuleSource::version` the context was built from
+    version: u64,
+}
+
+/// Sources that failed to load, retried once they change.
+struct Broken {
+    version: u64,
+    error: String,
+}
+
+/// Everything a worker needs to (re)build its context.
+#[derive(Clone)]
+struct WorkerConfig {
+    context_config: context::ContextConfig,
+    functions: HashMap<String, String>,
+    pages_dirs: Vec<String>,
+    page_pattern: String,
+    timeout: Option<Duration>,
+    #[cfg(feature = "tracing")]
+    console_tracing: bool,
+}
+
+impl WorkerConfig {
+    fn version(&self) -> u64 {
+        self.context_config
+            .js_src
+            .as_ref()
+            .map_or(0, |source| source.version())
+    }
+
+    fn new(config: &RuntimeConfig<'static>) -> Self {
+        let js_src = config.module_source.clone().or_else(|| {
+            config
+                .js_src_dir
+                .clone()
+                .map(|dir| Arc::new(dir) as Arc<dyn ModuleSource>)
+        });
+
+        let import_map = config.import_map.clone().or_else(|| {
+            let tsconfig = js_src.as_ref()?.read("tsconfig.json")?;
+            let tsconfig = String::from_utf8_lossy(&tsconfig);
+
+            ImportMap::from_tsconfig(&tsconfig)
+                .map_err(|err| log::warn!("ignoring tsconfig.json: {err}"))
+                .ok()
+        });
+
+        let context_config = context::ContextConfig {
+            js_src,
+            import_map,
+            memory_limit: config.memory_limit,
+            max_stack_size: config.max_stack_size,
+            gc_threshold: config.gc_threshold,
+            host_functions: config.host_functions.clone(),
+            source_maps: Default::default(),
+            tokio_handle: tokio::runtime::Handle::try_current().ok(),
+        };
+
+        Self {
+            context_config,
+            functions: config.functions.clone().unwrap_or_default(),
+            #[cfg(feature = "pages")]
+            pages_dirs: config.pages_dirs.clone(),
+            #[cfg(not(feature = "pages"))]
+            pages_dirs: Vec::new(),
             #[cfg(feature = "pages")]
-            pages_dir: "pages".into(),
+            page_pattern: config.page_pattern.clone(),
+            #[cfg(not(feature = "pages"))]
+            page_pattern: String::new(),
+            timeout: config.timeout,
+            #[cfg(feature = "tracing")]
+            console_tracing: config.console_tracing,
         }
     }
 }
 
+struct WorkerExit {
+    id: usize,
+    panicked: bool,
+    initialized: bool,
+}
+
+/// Reports the end of a worker thread to the supervisor, including panics.
+struct WorkerGuard {
+    id: usize,
+    initialized: bool,
+    exits: crossbeam::channel::Sender<WorkerExit>,
+}
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        _ = self.exits.send(WorkerExit {
+            id: self.id,
+            panicked: std::thread::panicking(),
+            initialized: self.initialized,
+        });
+    }
+}
+
+/// Delay before respawning a worker that crashed during initialization.
+const RESPAWN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// State shared by all clones of a runtime, its supervisor and workers.
+#[derive(Default)]
+struct Shared {
+    crashes: AtomicUsize,
+    /// Set once `shutdown` is called, no new scripts are accepted
+    closed: AtomicBool,
+    /// Held while a script is queued, so `closed` can't be set in between
+    sending: Mutex<()>,
+    /// Set when the shutdown deadline has passed, running scripts are interrupted
+    aborted: Arc<AtomicBool>,
+    /// By worker id, locked while spawning workers so that shutdown does not
+    /// miss a respawned worker
+    threads: Mutex<HashMap<usize, std::thread::JoinHandle<()>>>,
+}
+
 #[derive(Clone)]
 pub struct Runtime {
     sender: crossbeam::channel::Sender<Message>,
+    shared: Arc<Shared>,
+    workers: usize,
+    queue: Option<Arc<tokio::sync::Semaphore>>,
+    queue_policy: QueuePolicy,
 }
 
 impl Runtime {
+    /// Starts the workers. If a function or page fails to load, every script
+    /// fails with the error, use `try_new` to find out at startup.
     pub fn new(config: RuntimeConfig<'static>) -> Self {
-        context::init_module_loader(context::ContextConfig {
-            js_src: config.js_src_dir,
-        });
+        let worker_config = WorkerConfig::new(&config);
+        Runtime::start(config, worker_config)
+    }
 
+    /// Loads every function and page before starting the workers, and returns
+    /// all that fail as `Error::Compile`.
+    pub fn try_new(config: RuntimeConfig<'static>) -> Result<Self, Error> {
+        let worker_config = WorkerConfig::new(&config);
+        Runtime::validate(&worker_config)?;
+        Ok(Runtime::start(config, worker_config))
+    }
+
+    fn start(config: RuntimeConfig<'static>, worker_config: WorkerConfig) -> Self {
         let (sender, receiver) = crossbeam::channel::unbounded::<Message>();
+        let (exit_sender, exit_receiver) = crossbeam::channel::unbounded::<WorkerExit>();
+
+        let shared = Arc::new(Shared::default());
+
+        {
+            let mut threads = shared.threads.lock().unwrap();
+            for id in 0..config.workers {
+                threads.insert(
+                    id,
+                    Runtime::spawn_worker(
+                        id,
+                        receiver.clone(),
+                        worker_config.clone(),
+                        exit_sender.clone(),
+                        shared.clone(),
+                    ),
+                );
+            }
+        }
 
-        let functions = config.functions.unwrap_or_default();
+        Runtime::spawn_supervisor(
+            config.workers,
+            receiver,
+            worker_config,
+            exit_sender,
+            exit_receiver,
+            shared.clone(),
+        );
+
+        let queue = config
+            .queue_capacity
+            .map(|capacity| Arc::new(tokio::sync::Semaphore::new(capacity)));
 
-        for i in 0..config.workers {
-            let receiver = receiver.clone();
-            let functions = functions.clone();
-            Runtime::spawn_worker(receiver, functions, config.pages_dir.clone())
+        Self {
+            sender,
+            shared,
+            workers: config.workers,
+            queue,
+            queue_policy: config.queue_policy,
         }
+    }
 
-        Self { sender }
+    /// Number of worker threads that have crashed and been respawned.
+    pub fn worker_crashes(&self) -> usize {
+        self.shared.crashes.load(Ordering::Relaxed)
     }
 
-    fn spawn_worker(
+    /// Stops accepting new scripts, lets queued and running scripts finish and
+    /// joins the worker threads.
+    ///
+    /// Scripts still queued or running when `deadline` expires are cancelled.
+    /// Calling it again, or on another clone, has no effect.
+    pub async fn shutdown(&self, deadline: Duration) {
+        {
+            let _sending = self.shared.sending.lock().unwrap();
+            if self.shared.closed.swap(true, Ordering::SeqCst) {
+                return;
+            }
+        }
+
+        log::debug!("shutdown runtime");
+
+        // queued after all pending scripts, so the queue is drained first
+        for _ in 0..self.workers {
+            _ = self.sender.send(Message::Stop);
+        }
+
+        let threads = std::mem::take(&mut *self.shared.threads.lock().unwrap());
+
+        let mut join = tokio::task::spawn_blocking(move || {
+            for thread in threads.into_values() {
+                _ = thread.join();
+            }
+        });
+
+        if tokio::time::timeout(deadline, &mut join).await.is_err() {
+            log::warn!("shutdown deadline exceeded, cancelling remaining scripts");
+            self.shared.aborted.store(true, Ordering::SeqCst);
+            _ = join.await;
+        }
+
+        log::debug!("runtime stopped");
+    }
+
+    /// Respawns crashed workers until every worker has exited normally.
+    fn spawn_supervisor(
+        workers: usize,
         receiver: crossbeam::channel::Receiver<Message>,
-        functions: HashMap<String, String>,
-        pages_root: String,
+        worker_config: WorkerConfig,
+        exit_sender: crossbeam::channel::Sender<WorkerExit>,
+        exit_receiver: crossbeam::channel::Receiver<WorkerExit>,
+        shared: Arc<Shared>,
     ) {
         std::thread::spawn(move || {
-            log::debug!("spawn worker: {:?}", std::thread::current().id());
+            let mut alive = workers;
+
+            while alive > 0 {
+                let Ok(exit) = exit_receiver.recv() else {
+                    break;
+                };
+
+                if !exit.panicked {
+                    alive -= 1;
+                    continue;
+                }
+
+                shared.crashes.fetch_add(1, Ordering::Relaxed);
+                log::error!("worker {} crashed", exit.id);
+
+                if !exit.initialized {
+                    std::thread::sleep(RESPAWN_BACKOFF);
+                }
+
+                let mut threads = shared.threads.lock().unwrap();
+
+                if shared.closed.load(Ordering::SeqCst) {
+                    alive -= 1;
+                    continue;
+                }
+
+                log::debug!("respawn worker {}", exit.id);
+
+                // the crashed thread is done once it reported its exit
+                if let Some(thread) = threads.remove(&exit.id) {
+                    _ = thread.join();
+                }
+
+                threads.insert(
+                    exit.id,
+                    Runtime::spawn_worker(
+                        exit.id,
+                        receiver.clone(),
+                        worker_config.clone(),
+                        exit_sender.clone(),
+                        shared.clone(),
+                    ),
+                );
+            }
+
+            log::debug!("all workers stopped");
+        });
+    }
+
+    fn spawn_worker(
+        id: usize,
+        receiver: crossbeam::channel::Receiver<Message>,
+        config: WorkerConfig,
+        exits: crossbeam::channel::Sender<WorkerExit>,
+        shared: Arc<Shared>,
+    ) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            log::debug!("spawn worker {}: {:?}", id, std::thread::current().id());
 
-            let context = context::init()
-                .map_err(|e| log::error!("failed to initialize runtime context: {}", e))
-                .expect("Runtime context initialization failed");
+            let mut guard = WorkerGuard {
+                id,
+                initialized: false,
+                exits,
+            };
 
-            let mut compiled_fns = context::compile_functions(&context, functions).unwrap();
+            let interrupt = Box::new(context::Interrupt::new(shared.aborted.clone()));
 
-            let page_fns = Runtime::init_jsx_renderer(&context, pages_root).unwrap();
+            let mut worker = Runtime::start_worker_context(id, &config, &interrupt);
 
-            compiled_fns.extend(page_fns.into_iter());
+            guard.initialized = true;
 
             while let Ok(msg) = receiver.recv() {
                 match msg {
-                    Message::ExecuteScript { script, respond_to } => {
+                    Message::ExecuteScript {
+                        script,
+                        respond_to,
+                        permit,
+                        cancelled,
+                        json,
+                    } => {
+                        drop(permit);
+
+                        if cancelled.load(Ordering::Relaxed) || respond_to.is_closed() {
+                            log::trace!("skip cancelled script");
+                            continue;
+                        }
+
+                        if shared.aborted.load(Ordering::Relaxed) {
+                            _ = respond_to.send(Err(Error::ShuttingDown));
+                            continue;
+                        }
+
+                        // modules are cached per context, so changed sources need a new one
+                        let version = config.version();
+                        match &mut worker {
+                            Ok(current) if current.version != version => {
+                                log::info!("worker {id}: sources changed, reloading");
+                                match Runtime::try_init_worker_context(&config, &interrupt) {
+                                    Ok(reloaded) => worker = Ok(reloaded),
+                                    Err(err) => {
+                                        log::error!(
+                                            "worker {id}: reload failed, keeping the previous sources: {err}"
+                                        );
+                                        current.version = version;
+                                    }
+                                }
+                            }
+                            Err(broken) if broken.version != version => {
+                                log::info!("worker {id}: sources changed, loading");
+                                match Runtime::try_init_worker_context(&config, &interrupt) {
+                                    Ok(loaded) => worker = Ok(loaded),
+                                    Err(err) => {
+                                        log::error!(
+                                            "worker {id}: failed to load the sources: {err}"
+                                        );
+                                        *broken = Broken {
+                                            version,
+                                            error: err.to_string(),
+                                        };
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+
+                        let current = match &mut worker {
+                            Ok(current) => current,
+                            Err(broken) => {
+                                _ = respond_to.send(Err(Error::Unexpected(format!(
+                                    "failed to load the sources: {}",
+                                    broken.error
+                                ))));
+                                continue;
+                            }
+                        };
+
                         log::trace!("execute script");
 
-                        let source = Runtime::prepare_script(script, &compiled_fns);
+                        let timeout = script.timeout().or(config.timeout);
+                        let info = script.info(id);
+                        let source = Runtime::prepare_script(script, &current.compiled_fns);
+
+                        #[cfg(feature = "tracing")]
+                        let span = tracing::info_span!(
+                            "execute_script",
+                            worker = id,
+                            script = %info.script,
+                            page = %info.page
+                        );
+                        #[cfg(feature = "tracing")]
+                        let _entered = span.enter();
+
+                        let console = Console::new();
+                        #[cfg(feature = "tracing")]
+                        let console = match config.console_tracing {
+                            true => console.with_tracing(info),
+                            false => console,
+                        };
+
+                        interrupt.arm(timeout, Some(cancelled));
 
                         let msg = match source {
-                            Ok((args, source)) => context::eval(&context, args, source),
+                            Ok((args, source)) => context::eval(
+                                &current.context,
+                                &interrupt,
+                                console,
+                                args,
+                                source,
+                                json,
+                            ),
                             Err(err) => Err(err),
                         };
 
+                        let msg = match interrupt.disarm() {
+                            Some(context::Interrupted::Timeout) => {
+                                Err(Error::Timeout(timeout.unwrap_or_default()))
+                            }
+                            Some(context::Interrupted::Cancelled) => {
+                                log::trace!("script cancelled");
+                                Err(Error::Cancelled)
+                            }
+                            Some(context::Interrupted::Aborted) => Err(Error::ShuttingDown),
+                            None => msg,
+                        };
+
+                        let msg = msg.map_err(|err| match err {
+                            Error::Js(mut err) => {
+                                config.context_config.source_maps.remap(&mut err);
+                                Error::Js(err)
+                            }
+                            err => err,
+                        });
+
+                        // the heap may be left in an inconsistent state, but a context of
+                        // sources broken since is better than none. QuickJS unwinds
+                        // cleanly from a stack overflow, the context is kept.
+                        if let Err(err @ Error::OutOfMemory) = &msg {
+                            log::warn!("recycle worker context: {}", err);
+                            match Runtime::try_init_worker_context(&config, &interrupt) {
+                                Ok(recycled) => *current = recycled,
+                                Err(err) => log::error!(
+                                    "worker {id}: recycling failed, keeping the context: {err}"
+                                ),
+                            }
+                        }
+
                         _ = respond_to.send(msg);
                     }
+                    Message::Stop => break,
+                    #[cfg(test)]
+                    Message::Panic => panic!("worker {id} told to panic"),
                 };
             }
-        });
+
+            log::debug!("worker {} stopped", id);
+        })
+    }
+
+    /// Sources that fail to load would fail again in a respawned worker, so
+    /// the worker keeps running and answers every script with the error.
+    fn start_worker_context(
+        id: usize,
+        config: &WorkerConfig,
+        interrupt: &context::Interrupt,
+    ) -> Result<WorkerContext, Broken> {
+        Runtime::try_init_worker_context(config, interrupt).map_err(|err| {
+            log::error!("worker {id}: failed to load the sources: {err}");
+            Broken {
+                version: config.version(),
+                error: err.to_string(),
+            }
+        })
+    }
+
+    fn try_init_worker_context(
+        config: &WorkerConfig,
+        interrupt: &context::Interrupt,
+    ) -> Result<WorkerContext, Error> {
+        let version = config.version();
+
+        let context = context::init(&config.context_config)?;
+
+        context::set_interrupt_handler(&context, interrupt);
+
+        let mut compiled_fns = context::compile_functions(
+            &context,
+            config.functions.clone(),
+            &config.context_config.source_maps,
+        )?;
+
+        let page_fns = Runtime::init_jsx_renderer(&context, config)?;
+
+        compiled_fns.extend(page_fns);
+
+        Ok(WorkerContext {
+            compiled_fns,
+            context,
+            version,
+        })
     }
 
     fn init_jsx_renderer(
         context: &quickjs_rusty::Context,
-        pages_root: String,
-    ) -> Result<HashMap<String, JsCompiledFunction>, Error> {
+        config: &WorkerConfig,
+    ) -> Result<HashMap<String, CompiledFunction>, Error> {
         context.run_module("/jsx-runtime")?;
 
         let js_context = unsafe { context.context_raw() };
 
         let mut compiled_fns = HashMap::new();
 
-        struct Page {
-            path: String,
-            name: String,
-            ext: String,
-            import_name: String,
-            import_statement: String,
-        }
-
-        impl Page {
-            fn new(page: &include_dir::File) -> Self {
-                let path = page.path().to_str().unwrap();
-                let name = page.path().file_stem().unwrap().to_str().unwrap();
-                let ext = page.path().extension().unwrap().to_str().unwrap();
-
-                let import_name = name
-                    .split(".")
-                    .collect::<Vec<_>>()
-                    .first()
-                    .unwrap()
-                    .to_string();
-                let import_statement = format!("import * as {0} from '{1}'", import_name, path);
-
-                Self {
-                    path: path.into(),
-                    name: name.into(),
-                    ext: ext.into(),
-                    import_name,
-                    import_statement,
-                }
-            }
-        }
-
         #[cfg(all(feature = "transpiling", feature = "pages"))]
-        if let Some(root_dir) = context::get_js_dir() {
-            let mut pages: HashMap<String, Page> = HashMap::new();
-
-            root_dir
-                .find("**/*.page.[tj]sx")
-                .unwrap()
-                .filter_map(|f| match f {
-                    DirEntry::Dir(dir) => None,
-                    DirEntry::File(file) => Some(Page::new(file)),
-                })
-                .for_each(|page| {
-                    let name = page.name.to_string();
-                    if let Some(old_page) = pages.insert(name, page) {
-                        panic!(
-                            "Page name must be unique. Page '{}' has already been added at '{}'",
-                            old_page.name, old_page.path
-                        );
-                    }
-                });
+        if let Some(source) = &config.context_config.js_src {
+            let (pages, diagnostics) =
+                find_pages(source.as_ref(), &config.pages_dirs, &config.page_pattern);
 
-            let pages = pages.values().collect::<Vec<_>>();
+            if !diagnostics.is_empty() {
+                return Err(Error::Compile(diagnostics));
+            }
 
             let imports = pages
-                .iter()
-                .map(|page| page.import_statement.clone())
+                .values()
+                .enumerate()
+                .map(|(i, page)| format!("import * as __page{i} from {};", json!(page.path)))
                 .collect::<Vec<_>>()
                 .join("\n");
 
             let names = pages
-                .iter()
-                .map(|page| page.import_name.clone())
+                .values()
+                .enumerate()
+                .map(|(i, page)| format!("{}: __page{i}", json!(page.name)))
                 .collect::<Vec<_>>()
                 .join(", ");
 
@@ -239,12 +765,11 @@ impl Runtime {
             let view_names = context.eval_as::<Vec<String>>("globalThis.__viewNames;")?;
 
             for name in view_names {
-                let compiled_fn = quickjs_rusty::compile::compile(
+                let compiled_fn = CompiledFunction::compile(
                     js_context,
-                    &format!("globalThis.__views['{0}'](args);", name),
+                    &format!("globalThis.__views[{}](args);", json!(name)),
                     &name,
-                )?
-                .try_into_compiled_function()?;
+                )?;
 
                 compiled_fns.insert(name.to_string(), compiled_fn);
             }
@@ -253,14 +778,57 @@ impl Runtime {
         Ok(compiled_fns)
     }
 
+    /// Loads every function and page in a throwaway context and collects what fails.
+    fn validate(config: &WorkerConfig) -> Result<(), Error> {
+        let context = context::init(&config.context_config)?;
+        let mut diagnostics = Vec::new();
+
+        let mut functions = config.functions.iter().collect::<Vec<_>>();
+        functions.sort();
+
+        for (name, code) in functions {
+            let source_maps = &config.context_config.source_maps;
+            if let Err(err) = context::compile_function(&context, name, code.clone(), source_maps) {
+                diagnostics.push(Diagnostic::new(name, &err));
+            }
+        }
+
+        #[cfg(all(feature = "transpiling", feature = "pages"))]
+        if let Some(source) = &config.context_config.js_src {
+            context.run_module("/jsx-runtime")?;
+
+            let (pages, found) =
+                find_pages(source.as_ref(), &config.pages_dirs, &config.page_pattern);
+            diagnostics.extend(found);
+
+            for page in pages.values() {
+                let import = format!("import {};", json!(page.path));
+                if let Err(err) = context::eval_module(&context, &import) {
+                    let mut err = context::execution_error(err);
+                    // at the line of the `.tsx`, like errors of running pages
+                    if let Error::Js(err) = &mut err {
+                        config.context_config.source_maps.remap(err);
+                    }
+                    diagnostics.push(Diagnostic::new(&page.path, &err));
+                }
+            }
+        }
+
+        if diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Compile(diagnostics))
+        }
+    }
+
     fn prepare_script(
         script: Script,
-        compiled_fns: &HashMap<String, JsCompiledFunction>,
+        compiled_fns: &HashMap<String, CompiledFunction>,
     ) -> Result<(Option<Value>, Function), Error> {
         match script {
             #[cfg(feature = "transpiling")]
-            Script::Function { args, code } => Ok((args, Function::Code(code))),
-            Script::CompiledFunction { args, name } => {
+            Script::Function { args, code, .. } => Ok((args, Function::Code(code))),
+            Script::CompiledFunction { args, name, .. } => {
                 let function = compiled_fns
                     .get(&name)
                     .ok_or(Error::Unexpected(format!("function '{}' not found", name)))?
@@ -268,7 +836,7 @@ impl Runtime {
 
                 Ok((args, Function::Compiled(function)))
             }
-            Script::RenderPage { args, name } => {
+            Script::RenderPage { args, name, .. } => {
                 let function = compiled_fns
                     .get(&name)
                     .ok_or(Error::Unexpected(format!("page '{}' not found", name)))?
@@ -281,38 +849,83 @@ impl Runtime {
 
     #[cfg(all(feature = "with-axum", feature = "transpiling", feature = "pages"))]
     pub async fn render(&self, args: Value, page: &str) -> impl axum::response::IntoResponse {
-        let (sender, receiver) = tokio::sync::oneshot::channel::<Result<ScriptOutput, Error>>();
-
-        let msg = Message::ExecuteScript {
-            script: Script::RenderPage {
+        let res = self
+            .execute_script(Script::RenderPage {
                 args: Some(args),
                 name: page.into(),
-            },
-            respond_to: sender,
-        };
-
-        _ = self.sender.send(msg);
-
-        let res = receiver
-            .await
-            .map_err(|e| Error::Unexpected(e.to_string()))?;
+                timeout: None,
+            })
+            .await;
 
         res.map(|res| axum::response::Html(res.output))
     }
 
     pub async fn execute_script(&self, script: Script) -> Result<ScriptOutput, Error> {
+        self.execute(script, false).await
+    }
+
+    /// Like `execute_script`, but deserializes the value the script evaluates to,
+    /// converted like `JSON.stringify`. Values without JSON, e.g. `undefined`,
+    /// are `null`.
+    pub async fn execute_script_as<T: DeserializeOwned>(&self, script: Script) -> Result<T, Error> {
+        let res = self.execute(script, true).await?;
+        Ok(serde_json::from_str(&res.output)?)
+    }
+
+    async fn execute(&self, script: Script, json: bool) -> Result<ScriptOutput, Error> {
+        if self.shared.closed.load(Ordering::SeqCst) {
+            return Err(Error::ShuttingDown);
+        }
+
+        let permit = match &self.queue {
+            Some(queue) => Some(match self.queue_policy {
+                QueuePolicy::Wait => queue
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .map_err(|e| Error::Unexpected(e.to_string()))?,
+                QueuePolicy::Reject => queue
+                    .clone()
+                    .try_acquire_owned()
+                    .map_err(|_| Error::Overloaded)?,
+            }),
+            None => None,
+        };
+
         let (sender, receiver) = tokio::sync::oneshot::channel::<Result<ScriptOutput, Error>>();
 
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let mut cancel_on_drop = CancelOnDrop(Some(cancelled.clone()));
+
         let msg = Message::ExecuteScript {
             script,
             respond_to: sender,
+            permit,
+            cancelled,
+            json,
         };
 
-        _ = self.sender.send(msg);
+        {
+            // `shutdown` sets `closed` under the same lock, so the script is
+            // queued before the workers are told to stop or not at all
+            let _sending = self.shared.sending.lock().unwrap();
+
+            if self.shared.closed.load(Ordering::SeqCst) {
+                return Err(Error::ShuttingDown);
+            }
+
+            self.sender.send(msg).map_err(|_| Error::ShuttingDown)?;
+        }
 
+        // the sender is dropped without a response only if the worker died
         let res = receiver
             .await
-            .map_err(|e| Error::Unexpected(e.to_string()))?;
+            .map_err(|_| match self.shared.closed.load(Ordering::SeqCst) {
+                true => Error::ShuttingDown,
+                false => Error::WorkerCrashed,
+            })?;
+
+        cancel_on_drop.0 = None;
 
         res
     }
@@ -334,13 +947,111 @@ where
     }
 }
 
+struct Page {
+    /// Path in the module source
+    path: String,
+    /// Path relative to its pages directory, e.g. `admin/items`
+    name: String,
+}
+
+/// Pages in `pages_dirs` by name, sorted so every worker imports them in the
+/// same order, and the invalid patterns and name collisions.
+fn find_pages(
+    source: &dyn ModuleSource,
+    pages_dirs: &[String],
+    page_pattern: &str,
+) -> (BTreeMap<String, Page>, Vec<Diagnostic>) {
+    let mut pages: BTreeMap<String, Page> = BTreeMap::new();
+    let mut paths = HashSet::new();
+    let mut diagnostics = Vec::new();
+
+    for pages_dir in pages_dirs {
+        let pages_dir = pages_dir.trim_start_matches("./").trim_matches('/');
+
+        let pattern = if pages_dir.is_empty() {
+            format!("**/{page_pattern}")
+        } else {
+            if !source.is_dir(pages_dir) {
+                log::warn!("pages directory '{pages_dir}' not found");
+            }
+            format!("{pages_dir}/**/{page_pattern}")
+        };
+
+        let mut files = match glob::Pattern::new(&pattern) {
+            Ok(pattern) => source
+                .files(pages_dir)
+                .into_iter()
+                .filter(|path| pattern.matches(path))
+                .collect::<Vec<_>>(),
+            Err(err) => {
+                diagnostics.push(Diagnostic::from_message(
+                    &pattern,
+                    &format!("invalid page pattern: {err}"),
+                ));
+                continue;
+            }
+        };
+
+        files.sort();
+
+        for path in files {
+            // overlapping directories find the same file twice
+            if !paths.insert(path.clone()) {
+                continue;
+            }
+
+            let name = page_name(Path::new(&path), pages_dir);
+
+            if let Some(page) = pages.get(&name) {
+                diagnostics.push(Diagnostic::from_message(
+                    &path,
+                    &format!("page '{name}' is already defined by '{}'", page.path),
+                ));
+                continue;
+            }
+
+            pages.insert(name.clone(), Page { path, name });
+        }
+    }
+
+    (pages, diagnostics)
+}
+
+/// `admin/items.page.tsx` in `pages` is `admin/items`.
+fn page_name(path: &Path, pages_dir: &str) -> String {
+    let relative = path.strip_prefix(pages_dir).unwrap_or(path);
+    let file_name = relative.file_name().unwrap_or_default().to_string_lossy();
+    let stem = file_name.split('.').next().unwrap_or_default();
+
+    relative
+        .parent()
+        .into_iter()
+        .flat_map(|parent| parent.components())
+        .map(|component| component.as_os_str().to_string_lossy())
+        .chain([stem.into()])
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::SourceLocation;
     use quickjs_rusty::{Context, serde::to_js};
     use serde_json::json;
 
     use super::*;
 
+    /// Waits for something another thread does, instead of sleeping.
+    async fn until(condition: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("timed out waiting for the condition");
+    }
+
     #[tokio::test]
     async fn sum() {
         let runtime = Runtime::new(RuntimeConfig::default());
@@ -348,6 +1059,7 @@ mod tests {
             .execute_script(Script::Function {
                 code: "console.log('test'); 1 + 1".into(),
                 args: None,
+                timeout: None,
             })
             .await
             .unwrap();
@@ -359,6 +1071,7 @@ mod tests {
             .execute_script(Script::Function {
                 code: "console.log('test2'); 2 + 2".into(),
                 args: None,
+                timeout: None,
             })
             .await
             .unwrap();
@@ -368,67 +1081,625 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn ctx() {
+    async fn console_records() {
         let runtime = Runtime::new(RuntimeConfig::default());
         let res = runtime
             .execute_script(Script::Function {
-                code: "let obj = {name: ctx.name, args}; JSON.stringify(obj);".into(),
-                args: Some(json!(["a", "b"])),
+                code: r#"
+                    console.log('a', 1, {b: [true]});
+                    console.debug('noise');
+                    console.warn('careful', null);
+                    console.error(new Error('oops').message, () => 1);
+                "#
+                .into(),
+                args: None,
+                timeout: None,
             })
             .await
             .unwrap();
 
-        assert_eq!(res.output, "{\"name\":\"script\",\"args\":[\"a\",\"b\"]}");
-    }
-
-    #[cfg(feature = "transpiling")]
-    #[test]
-    fn test_transpile_ts() {
-        let source = "export type A = {args; any}; function a(args: A): {res: any} {};";
+        let records = &res.console;
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].level, crate::ConsoleLevel::Log);
         assert_eq!(
-            context::transpile_sript(source.into(), None).unwrap(),
-            "function a(args) {}\n"
+            records[0].values,
+            vec![json!("a"), json!(1), json!({"b": [true]})]
+        );
+        assert_eq!(records[1].level, crate::ConsoleLevel::Debug);
+        assert_eq!(records[2].values, vec![json!("careful"), Value::Null]);
+        assert_eq!(records[3].values, vec![json!("oops"), json!("() => 1")]);
+        assert!(records.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+        let warnings: Vec<_> = records
+            .iter()
+            .filter(|r| r.level >= crate::ConsoleLevel::Warn)
+            .collect();
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            serde_json::to_value(&records[2]).unwrap()["level"],
+            json!("warn")
         );
-    }
 
-    #[tokio::test]
-    async fn compile() {
-        let runtime = Runtime::new(RuntimeConfig {
-            functions: Some(HashMap::from([("sum.js".into(), "args.a+args.b".into())])),
-            ..Default::default()
-        });
+        // nothing is dropped from arrays
         let res = runtime
-            .execute_script(Script::CompiledFunction {
-                name: "sum.js".into(),
-                args: Some(json!({"a": 1, "b": 1})),
+            .execute_script(Script::Function {
+                code: "console.log([1, undefined, 2], {f() {}}, undefined, 10n)".into(),
+                args: None,
+                timeout: None,
             })
             .await
             .unwrap();
-
-        assert_eq!(res.output, "2");
+        assert_eq!(
+            res.console[0].values,
+            vec![
+                json!([1, null, 2]),
+                json!({}),
+                json!("undefined"),
+                json!("10")
+            ]
+        );
     }
 
-    #[cfg(feature = "transpiling")]
     #[tokio::test]
-    async fn compile_ts() {
+    async fn console_api() {
         let runtime = Runtime::new(RuntimeConfig {
-            functions: Some(HashMap::from([(
-                "sum.ts".into(),
-                r#"declare var args: { a: number; b: number };
-                function sum(a: number, b: number): number {
-                    const res = a + b;
-                    console.log(`a + b = ${res}`);
-                    return res;
-                }
-                sum(args.a, args.b);"#
-                    .into(),
-            )])),
+            workers: 1,
             ..Default::default()
         });
-        let res = runtime
-            .execute_script(Script::CompiledFunction {
-                name: "sum.ts".into(),
+
+        let console = |code: &str| {
+            let res = runtime.execute_script(Script::Function {
+                code: code.into(),
+                args: None,
+                timeout: None,
+            });
+            async { res.await.unwrap().console_output }
+        };
+
+        assert_eq!(
+            console("console.log({a: 1, b: 'x', c: [1, {d: {e: {f: 1}}}], 'my-key': null})").await,
+            "{ a: 1, b: 'x', c: [ 1, { d: [Object] } ], 'my-key': null }\n"
+        );
+        assert_eq!(
+            console("{ const a = {name: 'a'}; a.self = a; console.log(a, new Map([[1, 'one']]), new Set([1])) }").await,
+            "{ name: 'a', self: [Circular] } Map(1) { 1 => 'one' } Set(1) { 1 }\n"
+        );
+        assert_eq!(
+            console("class Foo { x = 1 }; console.log(new Foo(), Foo, () => 1, 10n, undefined)")
+                .await,
+            "Foo { x: 1 } [class Foo] [Function (anonymous)] 10n undefined\n"
+        );
+        assert_eq!(
+            console("console.log('%s is %d years, %j', 'Bob', 42.5, {a: 1}, 'extra')").await,
+            "Bob is 42.5 years, {\"a\":1} extra\n"
+        );
+        assert_eq!(
+            console("console.dir({a: {b: {c: {}}}}, {depth: 0})").await,
+            "{ a: [Object] }\n"
+        );
+        assert_eq!(
+            console(
+                "console.group('outer'); console.log('in'); console.groupEnd(); console.log('out')"
+            )
+            .await,
+            "outer\n  in\nout\n"
+        );
+        assert_eq!(
+            console(
+                "console.count(); console.count(); console.count('x'); console.countReset('y')"
+            )
+            .await,
+            "default: 1\ndefault: 2\nx: 1\nCount for 'y' does not exist\n"
+        );
+        assert_eq!(
+            console("console.assert(1 === 1, 'fine'); console.assert(false, 'broken', 1)").await,
+            "Assertion failed: broken 1\n"
+        );
+        assert_eq!(
+            console("console.table([{a: 1, b: 'x'}, {a: 2}])").await,
+            [
+                "┌─────────┬───┬─────┐",
+                "│ (index) │ a │ b   │",
+                "├─────────┼───┼─────┤",
+                "│ 0       │ 1 │ 'x' │",
+                "│ 1       │ 2 │     │",
+                "└─────────┴───┴─────┘\n",
+            ]
+            .join("\n")
+        );
+
+        let output = console("console.time('t'); console.timeEnd('t'); console.timeEnd('t')").await;
+        let lines: Vec<_> = output.lines().collect();
+        assert!(
+            lines[0].starts_with("t: ") && lines[0].ends_with("ms"),
+            "{output}"
+        );
+        assert_eq!(lines[1], "Timer 't' does not exist");
+
+        let output = console("function inner() { console.trace('here') }; inner()").await;
+        assert!(output.starts_with("Trace: here\n    at inner"), "{output}");
+
+        // counters start over for every script
+        assert_eq!(console("console.count()").await, "default: 1\n");
+
+        let res = runtime
+            .execute_script(Script::Function {
+                code: "console.table([1, 2]); console.count()".into(),
+                args: None,
+                timeout: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(res.console[0].values, vec![json!([1, 2])]);
+        assert_eq!(res.console[1].level, crate::ConsoleLevel::Info);
+        assert_eq!(res.console[1].values, vec![json!("default: 1")]);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn console_tracing() {
+        use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+        use tracing_subscriber::registry::LookupSpan;
+
+        #[derive(Debug, Default)]
+        struct Captured {
+            level: Option<tracing::Level>,
+            span: Option<String>,
+            fields: HashMap<String, String>,
+        }
+
+        impl tracing::field::Visit for Captured {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                self.fields
+                    .insert(field.name().into(), format!("{value:?}"));
+            }
+        }
+
+        struct Capture(Arc<Mutex<Vec<Captured>>>);
+
+        impl<S: tracing::Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Capture {
+            fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+                if event.metadata().target() != "js::console" {
+                    return;
+                }
+
+                let mut captured = Captured {
+                    level: Some(*event.metadata().level()),
+                    span: ctx.event_span(event).map(|span| span.name().into()),
+                    ..Default::default()
+                };
+                event.record(&mut captured);
+                self.0.lock().unwrap().push(captured);
+            }
+        }
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        tracing::subscriber::set_global_default(
+            tracing_subscriber::registry().with(Capture(events.clone())),
+        )
+        .unwrap();
+
+        let mut functions = HashMap::new();
+        functions.insert("greet".into(), "console.warn('hi', 1)".into());
+
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            functions: Some(functions),
+            console_tracing: true,
+            ..Default::default()
+        });
+
+        runtime
+            .execute_script(Script::CompiledFunction {
+                name: "greet".into(),
+                args: None,
+                timeout: None,
+            })
+            .await
+            .unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].level, Some(tracing::Level::WARN));
+        assert_eq!(events[0].span.as_deref(), Some("execute_script"));
+        assert_eq!(events[0].fields["message"], "hi 1");
+        assert_eq!(events[0].fields["script"], "greet");
+        assert_eq!(events[0].fields["worker"], "0");
+    }
+
+    #[tokio::test]
+    async fn ctx() {
+        let runtime = Runtime::new(RuntimeConfig::default());
+        let res = runtime
+            .execute_script(Script::Function {
+                code: "let obj = {name: ctx.name, args}; JSON.stringify(obj);".into(),
+                args: Some(json!(["a", "b"])),
+                timeout: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(res.output, "{\"name\":\"script\",\"args\":[\"a\",\"b\"]}");
+    }
+
+    #[tokio::test]
+    async fn typed_result() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Obj {
+            name: String,
+            args: Vec<String>,
+        }
+
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            ..Default::default()
+        });
+
+        let script = |code: &str| Script::Function {
+            code: code.into(),
+            args: Some(json!(["a", "b"])),
+            timeout: None,
+        };
+
+        let res: Value = runtime
+            .execute_script_as(script("({name: ctx.name, args})"))
+            .await
+            .unwrap();
+        assert_eq!(res, json!({"name": "script", "args": ["a", "b"]}));
+
+        let res: Obj = runtime
+            .execute_script_as(script("Promise.resolve({name: ctx.name, args})"))
+            .await
+            .unwrap();
+        assert_eq!(
+            res,
+            Obj {
+                name: "script".into(),
+                args: vec!["a".into(), "b".into()]
+            }
+        );
+
+        let res = runtime.execute_script_as::<Obj>(script("42")).await;
+        assert!(matches!(res, Err(Error::Json(_))));
+
+        // like `JSON.stringify`, nothing is dropped from arrays
+        let value = |code: &str| {
+            let res = runtime.execute_script_as::<Value>(script(code));
+            async { res.await.unwrap() }
+        };
+
+        assert_eq!(value("[1, undefined, 2]").await, json!([1, null, 2]));
+        assert_eq!(
+            value("({a: 1, f() {}, u: undefined})").await,
+            json!({"a": 1})
+        );
+        assert_eq!(value("new Map([[1, 2]])").await, json!({}));
+        assert_eq!(value("() => 1").await, Value::Null);
+        assert_eq!(value("undefined").await, Value::Null);
+        assert_eq!(value("10n").await, Value::Null);
+        assert_eq!(value("const o = {}; o.o = o; o").await, Value::Null);
+
+        let res = runtime
+            .execute_script_as::<Vec<Option<i32>>>(script("[1, undefined, 2]"))
+            .await
+            .unwrap();
+        assert_eq!(res, [Some(1), None, Some(2)]);
+    }
+
+    #[tokio::test]
+    async fn js_error() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            ..Default::default()
+        });
+
+        let eval = |code: &str| {
+            runtime.execute_script(Script::Function {
+                args: None,
+                code: code.into(),
+                timeout: None,
+            })
+        };
+
+        let res = eval("function inner() {\n  null.x\n}\ninner()").await;
+        let Err(Error::Js(err)) = res else {
+            panic!("expected a JS error, got {res:?}");
+        };
+
+        assert_eq!(err.name, "TypeError");
+        assert_eq!(err.message, "cannot read property 'x' of null");
+        assert_eq!(err.stack[0].function.as_deref(), Some("inner"));
+        assert_eq!(err.stack[1].function.as_deref(), Some("<eval>"));
+        assert_eq!(
+            err.location,
+            Some(SourceLocation {
+                file: "script.js".into(),
+                line: 2,
+                column: 3
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(&err).unwrap()["location"],
+            json!({"file": "script.js", "line": 2, "column": 3})
+        );
+        assert_eq!(
+            format!("{err:#}"),
+            "TypeError: cannot read property 'x' of null\n    at inner (script.js:2:3)\n    at <eval> (script.js:4:1)"
+        );
+
+        let res = eval("Promise.reject('nope')").await;
+        assert!(matches!(res, Err(Error::Js(err)) if err.name.is_empty() && err.message == "nope"));
+    }
+
+    #[cfg(feature = "transpiling")]
+    #[tokio::test]
+    async fn js_error_source_map() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            functions: Some(HashMap::from([(
+                "fail.ts".into(),
+                "type A = {\n  a: number;\n};\n\nconst a: A = { a: 1 };\nfunction fail(): never {\n  throw new Error('boom');\n}\nfail();".into(),
+            )])),
+            ..Default::default()
+        });
+
+        let res = runtime
+            .execute_script(Script::CompiledFunction {
+                args: None,
+                name: "fail.ts".into(),
+                timeout: None,
+            })
+            .await;
+        let Err(Error::Js(err)) = res else {
+            panic!("expected a JS error, got {res:?}");
+        };
+
+        assert_eq!(
+            err.location,
+            Some(SourceLocation {
+                file: "fail.ts".into(),
+                line: 7,
+                column: 13
+            })
+        );
+        assert_eq!(err.stack[1].location.as_ref().map(|l| l.line), Some(9));
+    }
+
+    #[tokio::test]
+    async fn promise() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            ..Default::default()
+        });
+
+        let eval = |code: &str| {
+            runtime.execute_script(Script::Function {
+                args: None,
+                code: code.into(),
+                timeout: None,
+            })
+        };
+
+        let res = eval("Promise.resolve(42)").await.unwrap();
+        assert_eq!(res.output, "42");
+
+        let res = eval("(async () => { const a = await Promise.resolve(1); return a + 1 })()")
+            .await
+            .unwrap();
+        assert_eq!(res.output, "2");
+
+        let res = eval("(async () => { throw new Error('boom') })()").await;
+        assert!(matches!(res, Err(Error::Js(err)) if err.to_string() == "Error: boom"));
+
+        let res = eval("new Promise(() => {})").await;
+        assert!(matches!(res, Err(Error::Execution(_))));
+    }
+
+    #[tokio::test]
+    async fn timers() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            timeout: Some(std::time::Duration::from_millis(200)),
+            ..Default::default()
+        });
+
+        let eval = |code: &str| {
+            runtime.execute_script(Script::Function {
+                args: None,
+                code: code.into(),
+                timeout: None,
+            })
+        };
+
+        let res = eval(
+            r#"{
+                const log = [];
+                setTimeout(() => log.push('timeout'), 10);
+                clearTimeout(setTimeout(() => log.push('cleared'), 5));
+                queueMicrotask(() => log.push('microtask'));
+                new Promise((resolve) => setTimeout(() => resolve(log.join()), 20));
+            }"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.output, "microtask,timeout");
+
+        let res = eval(
+            r#"{
+                let ticks = 0;
+                const id = setInterval(() => {
+                    console.log('tick', ++ticks);
+                    if (ticks === 3) clearInterval(id);
+                }, 1);
+            }"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.console_output, "tick 1\ntick 2\ntick 3\n");
+
+        let res = eval("setTimeout(() => { throw new Error('boom') }, 1)").await;
+        assert!(matches!(res, Err(Error::Js(err)) if err.to_string() == "Error: boom"));
+
+        // a forgotten interval runs into the timeout
+        let res = eval("setInterval(() => {}, 5)").await;
+        assert!(matches!(res, Err(Error::Timeout(_))));
+
+        // and does not leak into the next script
+        let res = eval("1 + 1").await.unwrap();
+        assert_eq!(res.output, "2");
+    }
+
+    #[tokio::test]
+    async fn internal_globals() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            js_src_dir: Some(include_dir::include_dir!(
+                "$CARGO_MANIFEST_DIR/tests/fixtures/packages"
+            )),
+            host_functions: HostFunctions::new()
+                .global("add", |(a, b): (i32, i32)| Ok::<_, String>(a + b)),
+            ..Default::default()
+        });
+
+        let eval = |code: &str| {
+            runtime.execute_script(Script::Function {
+                args: None,
+                code: code.into(),
+                timeout: None,
+            })
+        };
+
+        // the worker relies on them between scripts
+        let res = eval(
+            r#"Object.getOwnPropertyNames(globalThis)
+                .filter((name) => name.startsWith("__"))
+                .forEach((name) => { globalThis[name] = null; })"#,
+        )
+        .await;
+        assert!(res.is_ok(), "{res:?}");
+
+        let res = eval(
+            r#"console.log(add(1, 2));
+            import("./lib/throws.cjs").then((m) => new Promise((resolve) =>
+                setTimeout(resolve, 1, typeof m.default.fail)))"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.console_output, "3\n");
+        assert_eq!(res.output, "function");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn host_functions() {
+        #[derive(Deserialize, Serialize)]
+        struct User {
+            id: u64,
+            name: String,
+        }
+
+        let host_functions = HostFunctions::new()
+            .global("add", |(a, b): (i32, i32)| Ok::<_, String>(a + b))
+            .global("fail", |(): ()| Err::<(), _>("nope"))
+            .export_async("app", "user", |(id,): (u64,)| async move {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                Ok::<_, String>(User {
+                    id,
+                    name: format!("user {id}"),
+                })
+            });
+
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            host_functions,
+            ..Default::default()
+        });
+
+        let eval = |code: &str| {
+            runtime.execute_script(Script::Function {
+                args: None,
+                code: code.into(),
+                timeout: None,
+            })
+        };
+
+        let res = eval("add(1, 2)").await.unwrap();
+        assert_eq!(res.output, "3");
+
+        let res = eval("try { fail() } catch (e) { e.message }")
+            .await
+            .unwrap();
+        assert_eq!(res.output, "nope");
+
+        let res = eval(
+            r#"import("host:app").then(async ({ user }) => {
+                const [a, b] = await Promise.all([user(1), user(2)]);
+                return a.name + ", " + b.name;
+            })"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.output, "user 1, user 2");
+
+        let res = eval("import('host:app').then(({ user }) => user('x'))").await;
+        assert!(matches!(res, Err(Error::Js(err)) if err.message.contains("invalid type")));
+    }
+
+    #[test]
+    #[should_panic(expected = "host function name 'my-fn' is not a valid JS identifier")]
+    fn host_function_names() {
+        _ = HostFunctions::new().export("app", "my-fn", |(): ()| Ok::<_, String>(()));
+    }
+
+    #[cfg(feature = "transpiling")]
+    #[test]
+    fn test_transpile_ts() {
+        let source = "export type A = {args; any}; function a(args: A): {res: any} {};";
+        assert_eq!(
+            context::transpile_sript(source.into(), None).unwrap(),
+            "function a(args) {}\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn compile() {
+        let runtime = Runtime::new(RuntimeConfig {
+            functions: Some(HashMap::from([("sum.js".into(), "args.a+args.b".into())])),
+            ..Default::default()
+        });
+        let res = runtime
+            .execute_script(Script::CompiledFunction {
+                name: "sum.js".into(),
+                args: Some(json!({"a": 1, "b": 1})),
+                timeout: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(res.output, "2");
+    }
+
+    #[cfg(feature = "transpiling")]
+    #[tokio::test]
+    async fn compile_ts() {
+        let runtime = Runtime::new(RuntimeConfig {
+            functions: Some(HashMap::from([(
+                "sum.ts".into(),
+                r#"declare var args: { a: number; b: number };
+                function sum(a: number, b: number): number {
+                    const res = a + b;
+                    console.log(`a + b = ${res}`);
+                    return res;
+                }
+                sum(args.a, args.b);"#
+                    .into(),
+            )])),
+            ..Default::default()
+        });
+        let res = runtime
+            .execute_script(Script::CompiledFunction {
+                name: "sum.ts".into(),
                 args: Some(json!({"a": 1, "b": 1})),
+                timeout: None,
             })
             .await
             .unwrap();
@@ -476,6 +1747,7 @@ mod tests {
                 name:
                     "(props) => <div><ul>{props.items.map(({name}) => <li>{name}</li>)}</ul></div>"
                         .into(),
+                timeout: None,
             })
             .await
             .unwrap();
@@ -487,7 +1759,558 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn pool() {
+    async fn try_new() {
+        let config = |functions: &[(&str, &str)]| RuntimeConfig {
+            workers: 1,
+            functions: Some(
+                functions
+                    .iter()
+                    .map(|(name, code)| (name.to_string(), code.to_string()))
+                    .collect(),
+            ),
+            ..Default::default()
+        };
+
+        let Err(Error::Compile(diagnostics)) = Runtime::try_new(config(&[
+            ("ok.js", "args + 1"),
+            ("broken.js", "const a = 1;\nlet x = ;"),
+            ("typed.ts", "const x: number = ;"),
+        ])) else {
+            panic!("expected compile errors");
+        };
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].source, "broken.js");
+        assert!(diagnostics[0].message.starts_with("SyntaxError"));
+        assert_eq!(
+            diagnostics[0].location,
+            Some(SourceLocation {
+                file: "broken.js".into(),
+                line: 2,
+                column: 1
+            })
+        );
+        assert_eq!(diagnostics[1].source, "typed.ts");
+        assert_eq!(diagnostics[1].location.as_ref().map(|l| l.line), Some(1));
+
+        let err = Error::Compile(diagnostics).to_string();
+        assert!(
+            err.contains("\n  broken.js: broken.js:2:1: SyntaxError"),
+            "{err}"
+        );
+
+        let runtime = Runtime::try_new(config(&[("ok.js", "args + 1")])).unwrap();
+        let res = runtime
+            .execute_script(Script::CompiledFunction {
+                args: Some(json!(1)),
+                name: "ok.js".into(),
+                timeout: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(res.output, "2");
+    }
+
+    #[test]
+    fn page_names() {
+        let name = |path: &str, pages_dir: &str| page_name(Path::new(path), pages_dir);
+
+        assert_eq!(name("pages/items.page.tsx", "pages"), "items");
+        assert_eq!(name("pages/admin/items.page.tsx", "pages"), "admin/items");
+        assert_eq!(name("pages/shop/items.page.jsx", "pages"), "shop/items");
+        assert_eq!(name("web/pages/a/b/c.view.tsx", "web/pages"), "a/b/c");
+        assert_eq!(name("home.page.tsx", ""), "home");
+    }
+
+    #[cfg(all(feature = "transpiling", feature = "pages"))]
+    #[tokio::test]
+    async fn pages_dirs() {
+        let config = || RuntimeConfig {
+            workers: 1,
+            js_src_dir: Some(include_dir::include_dir!(
+                "$CARGO_MANIFEST_DIR/tests/fixtures/web"
+            )),
+            ..Default::default()
+        };
+
+        async fn render(runtime: &Runtime, name: &str) -> Result<ScriptOutput, Error> {
+            runtime
+                .execute_script(Script::RenderPage {
+                    args: Some(json!({"title": "hi"})),
+                    name: name.into(),
+                    timeout: None,
+                })
+                .await
+        }
+
+        // overlapping directories find users.page.tsx twice, the first one names it
+        let runtime = Runtime::new(RuntimeConfig {
+            pages_dirs: vec!["pages".into(), "./pages/admin/".into()],
+            ..config()
+        });
+        assert_eq!(
+            render(&runtime, "home").await.unwrap().output,
+            "<h1>hi</h1>"
+        );
+        assert_eq!(
+            render(&runtime, "admin/users").await.unwrap().output,
+            "<ul><li>admin</li></ul>"
+        );
+        assert!(render(&runtime, "users").await.is_err());
+        assert!(render(&runtime, "hidden").await.is_err());
+        assert!(render(&runtime, "about").await.is_err());
+
+        let runtime = Runtime::new(RuntimeConfig {
+            pages_dirs: vec!["extra".into()],
+            page_pattern: "*.view.jsx".into(),
+            ..config()
+        });
+        assert_eq!(
+            render(&runtime, "about").await.unwrap().output,
+            "<p>about</p>"
+        );
+        assert!(render(&runtime, "home").await.is_err());
+
+        // a name collision fails every script, the workers keep running
+        let runtime = Runtime::new(RuntimeConfig {
+            pages_dirs: vec!["collide/a".into(), "collide/b".into()],
+            ..config()
+        });
+        let res = render(&runtime, "items").await;
+        assert!(
+            matches!(&res, Err(Error::Unexpected(message)) if message.contains("page 'items' is already defined")),
+            "{res:?}"
+        );
+        assert_eq!(runtime.worker_crashes(), 0);
+    }
+
+    #[cfg(all(feature = "transpiling", feature = "pages"))]
+    #[tokio::test]
+    async fn separate_js_src_dirs() {
+        let site = Runtime::new(RuntimeConfig {
+            workers: 1,
+            js_src_dir: Some(include_dir::include_dir!(
+                "$CARGO_MANIFEST_DIR/tests/fixtures/web"
+            )),
+            ..Default::default()
+        });
+        let admin = Runtime::new(RuntimeConfig {
+            workers: 1,
+            js_src_dir: Some(include_dir::include_dir!(
+                "$CARGO_MANIFEST_DIR/tests/fixtures/admin"
+            )),
+            ..Default::default()
+        });
+
+        let home = |runtime: Runtime| async move {
+            runtime
+                .execute_script(Script::RenderPage {
+                    args: Some(json!({"title": "site"})),
+                    name: "home".into(),
+                    timeout: None,
+                })
+                .await
+                .unwrap()
+                .output
+        };
+
+        assert_eq!(home(site).await, "<h1>site</h1>");
+        assert_eq!(home(admin).await, "<h1>admin</h1>");
+    }
+
+    #[cfg(all(feature = "transpiling", feature = "pages"))]
+    #[tokio::test]
+    async fn module_resolution() {
+        let js_src_dir = || {
+            Some(include_dir::include_dir!(
+                "$CARGO_MANIFEST_DIR/tests/fixtures/resolve"
+            ))
+        };
+
+        // extensionless imports, a TS barrel file and a relative import from it
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            js_src_dir: js_src_dir(),
+            ..Default::default()
+        });
+        let res = runtime
+            .execute_script(Script::RenderPage {
+                args: None,
+                name: "home".into(),
+                timeout: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(res.output, "<ul><li>A</li></ul>");
+
+        let Err(Error::Compile(diagnostics)) = Runtime::try_new(RuntimeConfig {
+            workers: 1,
+            js_src_dir: js_src_dir(),
+            pages_dirs: vec!["broken".into()],
+            ..Default::default()
+        }) else {
+            panic!("expected a missing module");
+        };
+
+        let message = &diagnostics[0].message;
+        assert!(message.contains("'../components/nope'"), "{message}");
+        assert!(
+            message.ends_with(
+                "tried: components/nope, components/nope.{ts,tsx,js,jsx}, \
+                 components/nope/index.{ts,tsx,js,jsx}"
+            ),
+            "{message}"
+        );
+    }
+
+    #[cfg(all(feature = "transpiling", feature = "pages"))]
+    #[tokio::test]
+    async fn node_modules() {
+        let js_src_dir = || {
+            Some(include_dir::include_dir!(
+                "$CARGO_MANIFEST_DIR/tests/fixtures/packages"
+            ))
+        };
+
+        // exports conditions and patterns, a `module` field with a nested
+        // dependency, a scoped package
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            js_src_dir: js_src_dir(),
+            ..Default::default()
+        });
+        let res = runtime
+            .execute_script(Script::RenderPage {
+                args: None,
+                name: "home".into(),
+                timeout: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(res.output, "<p>[HELLO PKG!?]</p>");
+
+        let Err(Error::Compile(diagnostics)) = Runtime::try_new(RuntimeConfig {
+            workers: 1,
+            js_src_dir: js_src_dir(),
+            pages_dirs: vec!["broken".into()],
+            ..Default::default()
+        }) else {
+            panic!("expected unresolvable packages");
+        };
+
+        assert_eq!(diagnostics.len(), 1);
+        assert!(
+            diagnostics[0]
+                .message
+                .contains("'./hidden' is not exported by 'esm-exports'"),
+            "{}",
+            diagnostics[0]
+        );
+    }
+
+    #[cfg(all(feature = "transpiling", feature = "pages"))]
+    #[tokio::test]
+    async fn commonjs() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            js_src_dir: Some(include_dir::include_dir!(
+                "$CARGO_MANIFEST_DIR/tests/fixtures/packages"
+            )),
+            ..Default::default()
+        });
+
+        let run = |code: &str| {
+            runtime.execute_script(Script::Function {
+                args: None,
+                code: code.into(),
+                timeout: None,
+            })
+        };
+
+        // default imports of a package and a file, require of a package,
+        // a relative file and JSON inside them
+        let res = runtime
+            .execute_script(Script::RenderPage {
+                args: None,
+                name: "legacy".into(),
+                timeout: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(res.output, "<p>cjs!?</p>");
+
+        // the `require` condition of `exports`, modules are cached
+        let res = run(r#"
+            require("esm-exports").hello("cjs") + " " +
+                (require("./lib/legacy.cjs") === require("lib/legacy.cjs"))
+        "#)
+        .await
+        .unwrap();
+        assert_eq!(res.output, "hello cjs true");
+
+        let Err(Error::Js(err)) = run(r#"require("esm-exports/utils/upper")"#).await else {
+            panic!("expected an error");
+        };
+        assert!(err.message.contains("is an ES module"), "{}", err.message);
+
+        // frames point to the CommonJS file
+        let Err(Error::Js(err)) = run(r#"require("./lib/throws.cjs").fail()"#).await else {
+            panic!("expected an error");
+        };
+        assert_eq!(err.message, "from commonjs");
+        assert_eq!(
+            err.location.map(|l| (l.file, l.line)),
+            Some(("lib/throws.cjs".into(), 2))
+        );
+    }
+
+    #[cfg(all(feature = "transpiling", feature = "pages"))]
+    #[tokio::test]
+    async fn import_maps() {
+        async fn home(import_map: Option<ImportMap>) -> String {
+            let runtime = Runtime::new(RuntimeConfig {
+                workers: 1,
+                js_src_dir: Some(include_dir::include_dir!(
+                    "$CARGO_MANIFEST_DIR/tests/fixtures/aliases"
+                )),
+                import_map,
+                ..Default::default()
+            });
+
+            runtime
+                .execute_script(Script::RenderPage {
+                    args: None,
+                    name: "home".into(),
+                    timeout: None,
+                })
+                .await
+                .unwrap()
+                .output
+        }
+
+        // the paths of tsconfig.json
+        assert_eq!(home(None).await, "<li>tsconfig</li>");
+
+        let import_map = ImportMap::from_json(
+            r#"{
+                "imports": { "@/": "./src/", "config": "./src/config.ts" },
+                "scopes": { "./pages/": { "config": "./src/scoped.ts" } }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(home(Some(import_map)).await, "<li>scoped</li>");
+    }
+
+    #[cfg(all(feature = "transpiling", feature = "pages"))]
+    #[tokio::test]
+    async fn asset_imports() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            js_src_dir: Some(include_dir::include_dir!(
+                "$CARGO_MANIFEST_DIR/tests/fixtures/assets"
+            )),
+            ..Default::default()
+        });
+
+        let render = |name: &str| {
+            runtime.execute_script(Script::RenderPage {
+                args: None,
+                name: name.into(),
+                timeout: None,
+            })
+        };
+
+        // json with attributes in TS and JS, text by extension, attribute
+        // and `?raw`, bytes
+        let res = render("home").await.unwrap();
+        assert_eq!(res.output, "<p>hello|true|héllo|7|<b>raw</b>|assets</p>");
+
+        // multi-line attributes don't shift lines
+        let Err(Error::Js(err)) = render("lines").await else {
+            panic!("expected an error");
+        };
+        assert_eq!(err.message, "hello");
+        assert_eq!(
+            err.location.map(|l| (l.file, l.line)),
+            Some(("pages/lines.page.tsx".into(), 6))
+        );
+
+        let run = |code: &str| {
+            runtime.execute_script(Script::Function {
+                args: None,
+                code: code.into(),
+                timeout: None,
+            })
+        };
+
+        let res = run(r#"import("./assets/icon.svg", { with: { type: "bytes" } })
+                .then((m) => m.default[0])"#)
+        .await
+        .unwrap();
+        assert_eq!(res.output, "60");
+
+        // the attribute wins over the extension
+        let res = run(r#"import("./data/config.txt", { with: { type: "json" } })
+                .then((m) => m.default.name)"#)
+        .await
+        .unwrap();
+        assert_eq!(res.output, "config");
+
+        // strings, templates, comments and regexes aren't rewritten
+        let res = run(r#"import("./lib/strings.js")
+                .then((m) => [m.quoted, m.template, m.pattern].join("|"))"#)
+        .await
+        .unwrap();
+        assert_eq!(
+            res.output,
+            concat!(
+                r#"import x from "./data.json" with { type: "text" }|"#,
+                r#"from "./icon.svg" with { type: "bytes" } !|"#,
+                r#"from "x" with { type: "text" }"#,
+            )
+        );
+
+        let Err(Error::Js(err)) = run(r#"import("./data/broken.json")"#).await else {
+            panic!("expected an error");
+        };
+        assert!(err.message.contains("is not valid JSON"), "{}", err.message);
+    }
+
+    #[cfg(all(feature = "transpiling", feature = "pages"))]
+    #[tokio::test]
+    async fn hot_reload() {
+        let root = std::env::temp_dir().join(format!("js-hot-reload-{}", std::process::id()));
+        let pages = root.join("pages");
+        std::fs::create_dir_all(&pages).unwrap();
+        std::fs::write(
+            pages.join("home.page.tsx"),
+            "export default () => <h1>first</h1>;",
+        )
+        .unwrap();
+
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 2,
+            memory_limit: Some(16 * 1024 * 1024),
+            module_source: Some(Arc::new(
+                crate::DiskSource::new(&root).watch(Duration::from_millis(10)),
+            )),
+            ..Default::default()
+        });
+
+        let render = |name: &'static str| {
+            let runtime = runtime.clone();
+            async move {
+                runtime
+                    .execute_script(Script::RenderPage {
+                        args: None,
+                        name: name.into(),
+                        timeout: None,
+                    })
+                    .await
+                    .map(|res| res.output)
+            }
+        };
+
+        // every worker has to pick up the change, not just the next one
+        async fn eventually<F: Future<Output = Result<String, Error>>>(
+            render: impl Fn() -> F,
+            expected: &str,
+        ) {
+            for _ in 0..200 {
+                let outputs = [render().await, render().await];
+                if outputs
+                    .iter()
+                    .all(|output| output.as_deref().ok() == Some(expected))
+                {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            panic!("never rendered {expected}");
+        }
+
+        assert_eq!(render("home").await.unwrap(), "<h1>first</h1>");
+
+        std::fs::write(
+            pages.join("home.page.tsx"),
+            "export default () => <h1>second</h1>;",
+        )
+        .unwrap();
+        eventually(|| render("home"), "<h1>second</h1>").await;
+
+        std::fs::write(
+            pages.join("about.page.tsx"),
+            "export default () => <p>about</p>;",
+        )
+        .unwrap();
+        eventually(|| render("about"), "<p>about</p>").await;
+
+        // a broken page keeps the previous sources
+        std::fs::write(
+            pages.join("home.page.tsx"),
+            "export default () => <h1>{</h1>;",
+        )
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(render("home").await.unwrap(), "<h1>second</h1>");
+
+        // and so does recycling the context after running out of memory
+        for _ in 0..2 {
+            let res = runtime
+                .execute_script(Script::Function {
+                    args: None,
+                    code: "'x'.repeat(64 * 1024 * 1024)".into(),
+                    timeout: None,
+                })
+                .await;
+            assert!(matches!(res, Err(Error::OutOfMemory)));
+        }
+        eventually(|| render("home"), "<h1>second</h1>").await;
+        assert_eq!(runtime.worker_crashes(), 0);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(all(feature = "transpiling", feature = "pages"))]
+    #[test]
+    fn try_new_pages() {
+        let Err(Error::Compile(diagnostics)) = Runtime::try_new(RuntimeConfig {
+            workers: 1,
+            js_src_dir: Some(include_dir::include_dir!(
+                "$CARGO_MANIFEST_DIR/tests/fixtures/web"
+            )),
+            pages_dirs: vec!["collide/a".into(), "collide/b".into(), "broken".into()],
+            ..Default::default()
+        }) else {
+            panic!("expected page errors");
+        };
+
+        assert_eq!(diagnostics.len(), 3);
+        assert_eq!(diagnostics[0].source, "collide/b/items.page.tsx");
+        assert!(diagnostics[0].message.contains("collide/a/items.page.tsx"));
+        assert_eq!(diagnostics[1].source, "broken/bad.page.tsx");
+        assert_eq!(
+            diagnostics[1]
+                .location
+                .as_ref()
+                .map(|l| (l.file.as_str(), l.line)),
+            Some(("broken/bad.page.tsx", 2))
+        );
+
+        // thrown while loading, at the line of the `.tsx`
+        assert_eq!(diagnostics[2].source, "broken/throws.page.tsx");
+        assert_eq!(diagnostics[2].message, "Error: missing undefined");
+        assert_eq!(
+            diagnostics[2]
+                .location
+                .as_ref()
+                .map(|l| (l.file.as_str(), l.line)),
+            Some(("broken/throws.page.tsx", 7))
+        );
+    }
+
+    #[tokio::test]
+    async fn pool() {
         unsafe {
             std::env::set_var("RUST_LOG", "debug");
         }
@@ -500,6 +2323,7 @@ mod tests {
         let task1 = runtime.execute_script(Script::Function {
             args: None,
             code: "console.log('hello from first worker, loop forever'); while (true) {}".into(),
+            timeout: None,
         });
 
         let task2 = async {
@@ -507,6 +2331,7 @@ mod tests {
                 .execute_script(Script::Function {
                     args: None,
                     code: "console.log('hello from second worker');".into(),
+                    timeout: None,
                 })
                 .await;
         };
@@ -517,9 +2342,343 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn timeout() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            timeout: Some(std::time::Duration::from_millis(50)),
+            ..Default::default()
+        });
+
+        let res = runtime
+            .execute_script(Script::Function {
+                args: None,
+                code: "while (true) {}".into(),
+                timeout: None,
+            })
+            .await;
+
+        assert!(matches!(res, Err(Error::Timeout(_))));
+
+        let res = runtime
+            .execute_script(Script::Function {
+                args: None,
+                code: "let i = 0; while (true) { i++ }".into(),
+                timeout: Some(std::time::Duration::from_millis(10)),
+            })
+            .await;
+
+        assert!(matches!(res, Err(Error::Timeout(t)) if t.as_millis() == 10));
+
+        // the worker is still usable
+        let res = runtime
+            .execute_script(Script::Function {
+                args: None,
+                code: "1 + 1".into(),
+                timeout: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(res.output, "2");
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            memory_limit: Some(16 * 1024 * 1024),
+            max_stack_size: Some(256 * 1024),
+            functions: Some(HashMap::from([("sum.js".into(), "args.a+args.b".into())])),
+            ..Default::default()
+        });
+
+        let res = runtime
+            .execute_script(Script::Function {
+                args: None,
+                code: "'x'.repeat(64 * 1024 * 1024)".into(),
+                timeout: None,
+            })
+            .await;
+
+        assert!(matches!(res, Err(Error::OutOfMemory)));
+
+        // the recycled context still has compiled functions
+        let res = runtime
+            .execute_script(Script::CompiledFunction {
+                name: "sum.js".into(),
+                args: Some(json!({"a": 1, "b": 1})),
+                timeout: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(res.output, "2");
+
+        let res = runtime
+            .execute_script(Script::Function {
+                args: None,
+                code: "globalThis.kept = 1; function f() { return f() + 1 }; f()".into(),
+                timeout: None,
+            })
+            .await;
+
+        assert!(matches!(res, Err(Error::StackOverflow)));
+
+        // a script can throw the same errors
+        let res = runtime
+            .execute_script(Script::Function {
+                args: None,
+                code: "throw new RangeError('Maximum call stack size exceeded')".into(),
+                timeout: None,
+            })
+            .await;
+
+        assert!(res.is_err());
+
+        let res = runtime
+            .execute_script(Script::Function {
+                args: None,
+                code: "throw new Error('Maximum call stack size exceeded')".into(),
+                timeout: None,
+            })
+            .await;
+
+        assert!(matches!(res, Err(Error::Js(_))));
+
+        // neither recycles the context
+        let res = runtime
+            .execute_script(Script::Function {
+                args: None,
+                code: "globalThis.kept".into(),
+                timeout: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(res.output, "1");
+    }
+
+    #[tokio::test]
+    async fn respawn() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            ..Default::default()
+        });
+
+        runtime.sender.send(Message::Panic).unwrap();
+        until(|| runtime.worker_crashes() == 1).await;
+
+        let res = runtime
+            .execute_script(Script::Function {
+                code: "1 + 1".into(),
+                args: None,
+                timeout: None,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(res.output, "2");
+
+        // the handle of a crashed worker is replaced, not kept around
+        assert_eq!(runtime.shared.threads.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn broken_sources() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            functions: Some(HashMap::from([("broken.js".into(), "(".into())])),
+            ..Default::default()
+        });
+
+        let res = runtime
+            .execute_script(Script::Function {
+                code: "1 + 1".into(),
+                args: None,
+                timeout: None,
+            })
+            .await;
+
+        // the worker answers with the error instead of crashing over and over
+        assert!(
+            matches!(&res, Err(Error::Unexpected(message)) if message.starts_with("failed to load the sources")),
+            "{res:?}"
+        );
+        assert_eq!(runtime.worker_crashes(), 0);
+    }
+
+    #[tokio::test]
+    async fn overloaded() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            queue_capacity: Some(1),
+            queue_policy: QueuePolicy::Reject,
+            ..Default::default()
+        });
+
+        let busy = |runtime: Runtime| async move {
+            runtime
+                .execute_script(Script::Function {
+                    args: None,
+                    code: "while (true) {}".into(),
+                    timeout: Some(std::time::Duration::from_millis(500)),
+                })
+                .await
+        };
+
+        let queue = runtime.queue.clone().unwrap();
+        let queued = std::cell::Cell::new(false);
+
+        // `join!` starts them in order
+        let (running, queued_res, rejected) = tokio::join!(
+            busy(runtime.clone()),
+            async {
+                // the only worker frees the queue slot when it picks up `running`
+                until(|| queue.available_permits() == 1).await;
+                queued.set(true);
+                busy(runtime.clone()).await
+            },
+            async {
+                until(|| queued.get()).await;
+                busy(runtime.clone()).await
+            },
+        );
+
+        assert!(matches!(rejected, Err(Error::Overloaded)));
+        assert!(matches!(running, Err(Error::Timeout(_))));
+        assert!(matches!(queued_res, Err(Error::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn cancel() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            ..Default::default()
+        });
+
+        let forever = |runtime: Runtime| async move {
+            runtime
+                .execute_script(Script::Function {
+                    args: None,
+                    code: "while (true) {}".into(),
+                    timeout: None,
+                })
+                .await
+        };
+
+        let running = tokio::spawn(forever(runtime.clone()));
+        let queued = tokio::spawn(forever(runtime.clone()));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        running.abort();
+        queued.abort();
+
+        let res = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            runtime.execute_script(Script::Function {
+                args: None,
+                code: "1 + 1".into(),
+                timeout: None,
+            }),
+        )
+        .await
+        .expect("the worker should stop cancelled scripts")
+        .unwrap();
+
+        assert_eq!(res.output, "2");
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            ..Default::default()
+        });
+
+        let wait = |ms: u64| {
+            runtime.execute_script(Script::Function {
+                args: None,
+                code: format!(
+                    "{{ const end = Date.now() + {ms}; while (Date.now() < end) {{}} }} 'done'"
+                ),
+                timeout: None,
+            })
+        };
+
+        // `join!` queues both scripts before the shutdown starts
+        let (running, queued, ()) = tokio::join!(
+            wait(50),
+            wait(50),
+            runtime.shutdown(std::time::Duration::from_secs(5))
+        );
+
+        assert_eq!(running.unwrap().output, "done");
+        assert_eq!(queued.unwrap().output, "done");
+
+        let res = wait(0).await;
+        assert!(matches!(res, Err(Error::ShuttingDown)));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn shutdown_race() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 2,
+            ..Default::default()
+        });
+
+        // scripts sent while shutting down either run or are rejected
+        let scripts = (0..200)
+            .map(|_| {
+                let runtime = runtime.clone();
+                tokio::spawn(async move {
+                    runtime
+                        .execute_script(Script::Function {
+                            args: None,
+                            code: "1".into(),
+                            timeout: None,
+                        })
+                        .await
+                })
+            })
+            .collect::<Vec<_>>();
+
+        runtime.shutdown(Duration::from_secs(5)).await;
+
+        for script in scripts {
+            let res = script.await.unwrap();
+            assert!(
+                matches!(res, Ok(_) | Err(Error::ShuttingDown)),
+                "{:?}",
+                res.err()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn shutdown_deadline() {
+        let runtime = Runtime::new(RuntimeConfig {
+            workers: 1,
+            ..Default::default()
+        });
+
+        let forever = runtime.execute_script(Script::Function {
+            args: None,
+            code: "while (true) {}".into(),
+            timeout: None,
+        });
+
+        let (res, ()) = tokio::join!(
+            forever,
+            runtime.shutdown(std::time::Duration::from_millis(50))
+        );
+
+        assert!(matches!(res, Err(Error::ShuttingDown)));
+    }
+
     #[test]
     fn example() {
-        let console = context::Console::new();
+        let console = crate::console::Console::new();
         let output = console.output.clone();
 
         let context = Context::builder().console(console).build().unwrap();
@@ -534,7 +2693,7 @@ mod tests {
 
         let context = context.reset().unwrap();
 
-        let console = context::Console::new();
+        let console = crate::console::Console::new();
         let output = console.output.clone();
 
         _ = context.set_console(Box::new(console));
@@ -0,0 +1,49 @@
+use sourcemap::SourceMap;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::JsError;
+
+/// Source maps of transpiled modules and functions, by the file name QuickJS
+/// reports in stack traces. Shared by all workers of a runtime.
+#[derive(Clone, Default)]
+pub struct SourceMaps(Arc<RwLock<HashMap<String, SourceMap>>>);
+
+impl SourceMaps {
+    pub fn insert(&self, file: &str, source_map: &str) {
+        match SourceMap::from_slice(source_map.as_bytes()) {
+            Ok(source_map) => {
+                self.0.write().unwrap().insert(file.into(), source_map);
+            }
+            Err(err) => log::warn!("invalid source map for {file}: {err}"),
+        }
+    }
+
+    /// Rewrites the stack frames of transpiled files to the original line and column.
+    pub fn remap(&self, error: &mut JsError) {
+        let source_maps = self.0.read().unwrap();
+
+        for location in error
+            .stack
+            .iter_mut()
+            .filter_map(|frame| frame.location.as_mut())
+        {
+            let Some(source_map) = source_maps.get(&location.file) else {
+                continue;
+            };
+
+            // QuickJS counts from 1, source maps from 0
+            let token = source_map.lookup_token(
+                location.line.saturating_sub(1),
+                location.column.saturating_sub(1),
+            );
+
+            if let Some(token) = token {
+                location.line = token.get_src_line() + 1;
+                location.column = token.get_src_col() + 1;
+            }
+        }
+
+        error.location = error.stack.iter().find_map(|frame| frame.location.clone());
+    }
+}
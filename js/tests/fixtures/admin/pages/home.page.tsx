@@ -0,0 +1 @@
+export default () => <h1>admin</h1>;
@@ -0,0 +1,4 @@
+import { Item } from "@/components/item";
+import { name } from "config";
+
+export default () => <Item name={name} />;
@@ -0,0 +1 @@
+export const Item = ({ name }: { name: string }) => <li>{name}</li>;
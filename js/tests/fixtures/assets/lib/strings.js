@@ -0,0 +1,5 @@
+// looks like imports with attributes, but they're text
+export const quoted = 'import x from "./data.json" with { type: "text" }';
+export const template = `from "./icon.svg" with { type: "bytes" } ${"!"}`;
+// import x from "./data.json" with { type: "text" }
+export const pattern = /from "x" with { type: "text" }/.source;
@@ -0,0 +1,5 @@
+import messages from "../data/messages.json" with {
+  type: "json"
+};
+
+export const title = messages.title;
@@ -0,0 +1,17 @@
+import messages from "../data/messages.json" with { type: "json" };
+import icon from "../assets/icon.svg";
+import note from "../assets/note.txt" with { type: "text" };
+import bytes from "../assets/note.txt" with { type: "bytes" };
+import snippet from "../assets/snippet.html?raw";
+import { title } from "../lib/title.js";
+
+const parts = [
+  messages.greeting,
+  icon.startsWith("<svg"),
+  note.trim(),
+  bytes.length,
+  snippet.trim(),
+  title,
+];
+
+export default () => <p>{parts.join("|")}</p>;
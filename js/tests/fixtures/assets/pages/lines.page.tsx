@@ -0,0 +1,7 @@
+import messages from "../data/messages.json" with {
+  type: "json",
+};
+
+export default () => {
+  throw new Error(messages.greeting);
+};
@@ -0,0 +1,3 @@
+import { hidden } from "esm-exports/hidden";
+
+export default () => <p>{String(hidden)}</p>;
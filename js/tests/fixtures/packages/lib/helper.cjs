@@ -0,0 +1 @@
+exports.exclaim = (value) => value + "!";
@@ -0,0 +1,5 @@
+const identity = require("cjs-only");
+const { exclaim } = require("./helper.cjs");
+const { suffix } = require("./data.json");
+
+module.exports = (value) => exclaim(identity(value)) + suffix;
@@ -0,0 +1,3 @@
+exports.fail = () => {
+  throw new Error("from commonjs");
+};
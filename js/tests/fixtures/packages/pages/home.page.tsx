@@ -0,0 +1,6 @@
+import { hello } from "esm-exports";
+import { upper } from "esm-exports/utils/upper";
+import { decorate } from "legacy-module";
+import brackets from "@scope/typed";
+
+export default () => <p>{brackets(decorate(upper(hello("pkg"))))}</p>;
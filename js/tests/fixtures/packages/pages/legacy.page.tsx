@@ -0,0 +1,4 @@
+import identity from "cjs-only";
+import legacy from "../lib/legacy.cjs";
+
+export default () => <p>{legacy(identity("cjs"))}</p>;
@@ -0,0 +1,3 @@
+import { Item } from "../components/nope";
+
+export default () => <Item name="a" />;
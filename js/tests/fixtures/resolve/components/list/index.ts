@@ -0,0 +1 @@
+export { List } from "./list";
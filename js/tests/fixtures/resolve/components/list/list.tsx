@@ -0,0 +1,3 @@
+export const List = ({ children }: { children?: unknown }) => (
+  <ul>{children}</ul>
+);
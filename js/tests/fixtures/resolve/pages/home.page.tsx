@@ -0,0 +1,9 @@
+import { Item } from "../components/item";
+import { List } from "../components/list";
+import { upper } from "../lib/format";
+
+export default () => (
+  <List>
+    <Item name={upper("a")} />
+  </List>
+);
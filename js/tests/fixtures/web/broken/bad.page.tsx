@@ -0,0 +1,3 @@
+export default () => {
+  return <p>{</p>;
+};
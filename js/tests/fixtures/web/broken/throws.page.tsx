@@ -0,0 +1,9 @@
+type Props = {
+  name: string;
+};
+
+const config: Record<string, string> = {};
+
+throw new Error(`missing ${config.key}`);
+
+export default (props: Props) => <p>{props.name}</p>;
@@ -0,0 +1 @@
+export default () => <p>a</p>;
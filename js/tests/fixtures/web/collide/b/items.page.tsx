@@ -0,0 +1 @@
+export default () => <p>b</p>;
@@ -0,0 +1 @@
+export default () => <p>about</p>;
@@ -0,0 +1 @@
+export default () => <p>not a page root</p>;
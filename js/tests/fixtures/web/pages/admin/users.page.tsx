@@ -0,0 +1 @@
+export default () => <ul><li>admin</li></ul>;
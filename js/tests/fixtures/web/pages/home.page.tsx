@@ -0,0 +1 @@
+export default ({ title }: { title: string }) => <h1>{title}</h1>;